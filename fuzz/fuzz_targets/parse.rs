@@ -0,0 +1,34 @@
+//! Feeds arbitrary text through every public parsing function, none of them may panic.
+//!
+//! Run with `cargo fuzz run parse` from the root of the repository.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use RAMulator::assembler::assemble;
+use RAMulator::diagnostics::diagnose_json;
+use RAMulator::format::format_source;
+use RAMulator::import::from_json;
+use RAMulator::new_parser::NewParser;
+use RAMulator::parse_integer;
+use RAMulator::parser::{validate, Parser};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    let _ = Parser::default().parse(source);
+    let _ = Parser::default().parse_source_new(source.to_string());
+    let _ = Parser::default().parse_source(source.to_string());
+    let _ = validate(source);
+    let _ = assemble(source);
+    let _ = diagnose_json(source);
+    let _ = format_source(source);
+    NewParser::default().parse_source(source);
+    let _ = from_json(source);
+
+    for word in source.split_whitespace() {
+        let _ = parse_integer(word);
+    }
+});
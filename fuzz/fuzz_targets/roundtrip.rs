@@ -0,0 +1,28 @@
+//! Decodes arbitrary bytes into instructions and checks that every valid program survives
+//! encoding, decoding, disassembling and parsing again unchanged.
+//!
+//! Run with `cargo fuzz run roundtrip` from the root of the repository.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use RAMulator::bytecode::{decode, roundtrip};
+use RAMulator::parser::validate_jump_targets;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(instructions) = decode(data) else {
+        return;
+    };
+
+    // Only programs that the parser accepts can be read back from text
+    if !instructions.iter().all(|inst| inst.has_valid_operand()) {
+        return;
+    }
+    if validate_jump_targets(&instructions).is_err() {
+        return;
+    }
+
+    if let Err(error) = roundtrip(&instructions) {
+        panic!("{error}");
+    }
+});
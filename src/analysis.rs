@@ -0,0 +1,155 @@
+use alloc::{collections::BTreeSet, string::String, vec::Vec};
+
+use crate::{Instruction, OpCode, OpType};
+
+/// Non-fatal problems found in the source. They do not prevent the program from running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// Instruction at `index`, declared in `line`, can never be executed
+    UnreachableInstruction { index: usize, line: usize },
+    /// Label is declared, but never used
+    UnusedLabel { label: String },
+    /// Instruction at `index`, declared in `line`, always divides by zero (`DIV =0`)
+    DivisionByConstantZero { index: usize, line: usize },
+    /// Literal in `line` does not fit in a register, its value is truncated to 32 bits
+    ConstantOverflow { line: usize, literal: String },
+    /// Instruction at `index`, declared in `line`, jumps to itself and never stops (`loop: JUMP loop`)
+    TrivialInfiniteLoop { index: usize, line: usize },
+    /// `END` directive in `line` is followed by more source, which is not parsed
+    IgnoredAfterEnd { line: usize },
+}
+
+impl core::fmt::Display for Warning {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Warning::UnreachableInstruction { index, line } => {
+                write!(f, "WARNING: Instruction number {index} in line {line} can never be executed.")
+            }
+            Warning::UnusedLabel { label } => {
+                write!(f, "WARNING: Label `{label}` is declared, but never used.")
+            }
+            Warning::DivisionByConstantZero { index, line } => {
+                write!(f, "WARNING: Instruction number {index} in line {line} always divides by zero.")
+            }
+            Warning::ConstantOverflow { line, literal } => {
+                write!(f, "WARNING: Number `{literal}` in line {line} does not fit in 32 bits and gets truncated.")
+            }
+            Warning::TrivialInfiniteLoop { index, line } => {
+                write!(f, "WARNING: Instruction number {index} in line {line} jumps to itself and never stops.")
+            }
+            Warning::IgnoredAfterEnd { line } => {
+                write!(f, "WARNING: Source after the END directive in line {line} is ignored.")
+            }
+        }
+    }
+}
+
+/// Finds instructions that can never be executed.
+///
+/// An instruction is considered dead when it follows an unconditional `HALT` or `JUMP` and no
+/// jump points at it or at any instruction between it and the `HALT`/`JUMP`. `jump_targets`
+/// yields instruction indices that can be jumped to, for example the ones labels point to. Any
+/// collection of indices can be passed, such as a set, a slice iterator or map values.
+///
+/// Returns indices of the dead instructions in ascending order.
+pub fn find_unreachable(instrs: &[Instruction], jump_targets: impl IntoIterator<Item = usize>) -> Vec<usize> {
+    let jump_targets: BTreeSet<usize> = jump_targets.into_iter().collect();
+    let mut unreachable = Vec::new();
+    let mut reachable = true;
+
+    for (idx, inst) in instrs.iter().enumerate() {
+        // Jumping into the region makes it (and everything after it) reachable again
+        if jump_targets.contains(&idx) {
+            reachable = true;
+        }
+
+        if !reachable {
+            unreachable.push(idx);
+        }
+
+        if matches!(inst.op_code, OpCode::HALT | OpCode::JUMP) {
+            reachable = false;
+        }
+    }
+
+    unreachable
+}
+
+/// Finds instruction indices that jumps with a literal argument, such as `JUMP =2` or `JGTZ 3`,
+/// or a relative one, such as `JUMP ~2`, point to. Jumps through a register (`JUMP *1`) are not
+/// resolved.
+///
+/// Returns the indices in the order of the jumps, possibly repeated.
+pub fn find_jump_targets(instrs: &[Instruction]) -> Vec<usize> {
+    instrs
+        .iter()
+        .enumerate()
+        .filter(|(_, inst)| inst.op_code.is_jump())
+        .filter_map(|(idx, inst)| match inst.op_type {
+            OpType::Register | OpType::Value => usize::try_from(inst.op_value).ok(),
+            OpType::Relative => idx.checked_add_signed(inst.op_value as isize),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Finds instructions that always divide by zero, such as `DIV =0`.
+///
+/// Returns indices of the instructions in ascending order.
+pub fn find_division_by_zero(instrs: &[Instruction]) -> Vec<usize> {
+    instrs
+        .iter()
+        .enumerate()
+        .filter(|(_, inst)| inst.op_code == OpCode::DIV && inst.op_type == OpType::Value && inst.op_value == 0)
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/// Finds unconditional jumps that point at themselves, such as `loop: JUMP loop`.
+///
+/// Returns indices of the instructions in ascending order.
+pub fn find_trivial_infinite_loops(instrs: &[Instruction]) -> Vec<usize> {
+    instrs
+        .iter()
+        .enumerate()
+        .filter(|(idx, inst)| {
+            let jumps_to_itself = match inst.op_type {
+                OpType::Register | OpType::Value => usize::try_from(inst.op_value).is_ok_and(|target| target == *idx),
+                OpType::Relative => inst.op_value == 0,
+                _ => false,
+            };
+            inst.op_code == OpCode::JUMP && jumps_to_itself
+        })
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn unreachable_with_any_targets() {
+        let program = Parser::default().parse("JUMP =2\nHALT\nWRITE =5\nHALT").unwrap().instructions;
+
+        assert_eq!(find_unreachable(&program, []), [1, 2, 3]);
+        assert_eq!(find_unreachable(&program, [2]), [1]);
+        assert_eq!(find_unreachable(&program, BTreeSet::from([1])), [2, 3]);
+        assert_eq!(find_unreachable(&program, [3, 2].iter().copied()), [1]);
+    }
+
+    #[test]
+    fn literal_jump_targets() {
+        let program = Parser::default().parse("JUMP =2\nHALT\nJGTZ 4\nJZERO *1\nHALT").unwrap().instructions;
+
+        assert_eq!(find_jump_targets(&program), [2, 4]);
+    }
+
+    #[test]
+    fn relative_jump_targets() {
+        let program = Parser::default().parse("LOAD =1\nJUMP ~2\nJGTZ ~-2\nJZERO ~0\nHALT").unwrap().instructions;
+
+        assert_eq!(find_jump_targets(&program), [3, 0, 3]);
+    }
+}
@@ -0,0 +1,132 @@
+use alloc::{collections::BTreeMap, format, string::{String, ToString}, vec::Vec};
+
+use crate::{source_lines, Instruction};
+use crate::parser::{ParseError, Parser};
+
+/// Single line of the assembler listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingEntry {
+    /// Index of the instruction in the instruction stack
+    pub address: usize,
+    /// Instruction with labels resolved to addresses
+    pub instruction: Instruction,
+    /// Source line (or statement of the line) the instruction was parsed from
+    pub source: String,
+}
+
+/// Program with every label resolved to a numeric address, produced by [`assemble`].
+///
+/// Printing it produces a classic assembler listing: address, decoded instruction and the original
+/// line, followed by the symbol table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Listing {
+    pub entries: Vec<ListingEntry>,
+    /// Labels and addresses they point to
+    pub symbols: BTreeMap<String, usize>,
+}
+
+/// Label together with the instructions that refer to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XrefEntry {
+    pub label: String,
+    /// Index of the instruction the label points to
+    pub address: usize,
+    /// Indices of the instructions using the label as their argument, in ascending order
+    pub references: Vec<usize>,
+}
+
+/// Symbol cross-reference of a program, produced by [`cross_reference`].
+///
+/// Printing it produces a classic assembler cross-reference table: every label, its address and
+/// the addresses of the instructions referring to it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XrefReport {
+    /// Entries sorted by the label
+    pub entries: Vec<XrefEntry>,
+}
+
+/// Parses the source and pairs every instruction with its address and source line
+pub fn assemble(source: &str) -> Result<Listing, ParseError> {
+    let output = Parser::default().parse(source)?;
+    let lines: Vec<&str> = source_lines(source).collect();
+
+    // Lines with several statements are split, so that every entry shows only its own statement
+    let entries = output.instructions
+        .into_iter()
+        .zip(output.source_lines)
+        .enumerate()
+        .map(|(address, (instruction, line))| {
+            let source = match instruction.source() {
+                Some(source) => source.text.clone(),
+                None => lines[line - 1].trim().to_string(),
+            };
+            ListingEntry { address, instruction, source }
+        })
+        .collect();
+
+    Ok(Listing {
+        entries,
+        symbols: output.label_map.into_iter().collect(),
+    })
+}
+
+/// Parses the source and lists every label with the instruction it points to and the instructions
+/// that use it as their argument
+pub fn cross_reference(source: &str) -> Result<XrefReport, ParseError> {
+    let mut parser = Parser::default();
+    let output = parser.parse(source)?;
+
+    let mut references: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (label, index) in parser.label_uses() {
+        references.entry(label).or_default().push(index);
+    }
+
+    let symbols: BTreeMap<&String, &usize> = output.label_map.iter().collect();
+    let entries = symbols
+        .into_iter()
+        .map(|(label, address)| {
+            let mut references = references.remove(label.as_str()).unwrap_or_default();
+            references.sort();
+            XrefEntry { label: label.clone(), address: *address, references }
+        })
+        .collect();
+
+    Ok(XrefReport { entries })
+}
+
+impl core::fmt::Display for XrefReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "SYMBOL           ADDR  REFERENCES")?;
+        for entry in &self.entries {
+            let references: Vec<String> = entry.references
+                .iter()
+                .map(|address| format!("{address:04}"))
+                .collect();
+            let line = format!("{:<16} {:04}  {}", entry.label, entry.address, references.join(" "));
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl core::fmt::Display for Listing {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "ADDR  INSTRUCTION     SOURCE")?;
+        for entry in &self.entries {
+            let inst = &entry.instruction;
+            let op_code = inst.mnemonic();
+            writeln!(f, "{:04}  {op_code:<6} {:<8} {}", entry.address, inst.operand_text(), entry.source)?;
+        }
+
+        if !self.symbols.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "SYMBOL           ADDR")?;
+            for (label, address) in &self.symbols {
+                writeln!(f, "{label:<16} {address:04}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
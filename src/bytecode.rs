@@ -0,0 +1,348 @@
+use alloc::{format, string::String, vec::Vec};
+
+use crate::parser::{ParseError, Parser};
+use crate::{Instruction, OpCode, OpType};
+
+/// Number of bytes taken by a single encoded instruction: opcode, operand type and a 32 bit
+/// little endian value. Instructions with a second argument, such as `SET 5, =42`, are followed
+/// by its type and value in [`SECOND_OPERAND_SIZE`] more bytes
+pub const INSTRUCTION_SIZE: usize = 6;
+
+/// Number of bytes taken by the second argument: operand type and a 32 bit little endian value
+pub const SECOND_OPERAND_SIZE: usize = 5;
+
+/// Errors that can occur while decoding bytecode
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Bytecode of the given length ends in the middle of an instruction
+    TruncatedInstruction { length: usize },
+    /// Instruction number `index` has an opcode that does not exist
+    UnknownOpcode { index: usize, opcode: u8 },
+    /// Instruction number `index` has an operand type that does not exist
+    UnknownOperandType { index: usize, op_type: u8 },
+    /// Instruction number `index` has no operand, but its value is not zero
+    UnexpectedValue { index: usize },
+}
+
+impl core::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DecodeError::TruncatedInstruction { length } => {
+                write!(f, "ERROR: Bytecode of length {length} ends in the middle of an instruction")
+            }
+            DecodeError::UnknownOpcode { index, opcode } => {
+                write!(f, "ERROR: Instruction number {index} has opcode {opcode}, which does not exist")
+            }
+            DecodeError::UnknownOperandType { index, op_type } => {
+                write!(f, "ERROR: Instruction number {index} has operand type {op_type}, which does not exist")
+            }
+            DecodeError::UnexpectedValue { index } => {
+                write!(f, "ERROR: Instruction number {index} has no operand, but its value is not zero")
+            }
+        }
+    }
+}
+
+impl core::error::Error for DecodeError {}
+
+/// Errors that can occur in [`roundtrip`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum RoundtripError {
+    /// Encoded instructions could not be decoded
+    Decode(DecodeError),
+    /// Disassembled text could not be parsed
+    Parse(ParseError),
+    /// Number of instructions changed on the way
+    LengthMismatch { expected: usize, found: usize },
+    /// Instruction number `index` changed on the way
+    Mismatch { index: usize },
+}
+
+impl core::fmt::Display for RoundtripError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RoundtripError::Decode(error) => write!(f, "{error}"),
+            RoundtripError::Parse(error) => write!(f, "{error}"),
+            RoundtripError::LengthMismatch { expected, found } => {
+                write!(f, "ERROR: Expected {expected} instructions after the round trip, found {found}")
+            }
+            RoundtripError::Mismatch { index } => {
+                write!(f, "ERROR: Instruction number {index} changed during the round trip")
+            }
+        }
+    }
+}
+
+impl core::error::Error for RoundtripError {}
+
+fn opcode_from_byte(byte: u8) -> Option<OpCode> {
+    let op_code = match byte {
+        0 => OpCode::LOAD,
+        1 => OpCode::STORE,
+        2 => OpCode::ADD,
+        3 => OpCode::SUB,
+        4 => OpCode::MULT,
+        5 => OpCode::DIV,
+        6 => OpCode::READ,
+        7 => OpCode::WRITE,
+        8 => OpCode::JUMP,
+        9 => OpCode::JGTZ,
+        10 => OpCode::JZERO,
+        11 => OpCode::HALT,
+        12 => OpCode::JNEG,
+        13 => OpCode::JNZ,
+        14 => OpCode::SWAP,
+        15 => OpCode::DUMP,
+        16 => OpCode::PUSH,
+        17 => OpCode::POP,
+        19 => OpCode::RAND,
+        20 => OpCode::CLEAR,
+        21 => OpCode::SET,
+        _ => return None,
+    };
+    Some(op_code)
+}
+
+fn op_type_from_byte(byte: u8) -> Option<OpType> {
+    let op_type = match byte {
+        0 => OpType::Register,
+        1 => OpType::Value,
+        2 => OpType::ReadReg,
+        3 => OpType::NoValue,
+        4 => OpType::Indexed,
+        5 => OpType::Relative,
+        _ => return None,
+    };
+    Some(op_type)
+}
+
+/// Encodes the instructions into bytecode, [`INSTRUCTION_SIZE`] bytes per instruction (plus the
+/// second argument of the instructions that have one). Comments
+/// and source lines are not encoded. Neither are mnemonics of extension instructions, so they
+/// cannot be decoded back
+pub fn encode(instrs: &[Instruction]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(instrs.len() * INSTRUCTION_SIZE);
+    for inst in instrs {
+        bytes.push(inst.op_code.clone() as u8);
+        bytes.push(inst.op_type.clone() as u8);
+        bytes.extend_from_slice(&inst.op_value.to_le_bytes());
+        if let Some((op_type, value)) = inst.second_operand() {
+            bytes.push(op_type.clone() as u8);
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+/// Decodes instructions encoded with [`encode`]
+pub fn decode(bytes: &[u8]) -> Result<Vec<Instruction>, DecodeError> {
+    let truncated = DecodeError::TruncatedInstruction { length: bytes.len() };
+    let mut instructions = Vec::new();
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let index = instructions.len();
+        let Some((chunk, tail)) = rest.split_first_chunk::<INSTRUCTION_SIZE>() else {
+            return Err(truncated);
+        };
+        rest = tail;
+
+        let op_code = opcode_from_byte(chunk[0])
+            .ok_or(DecodeError::UnknownOpcode { index, opcode: chunk[0] })?;
+        let op_type = op_type_from_byte(chunk[1])
+            .ok_or(DecodeError::UnknownOperandType { index, op_type: chunk[1] })?;
+        let op_value = i32::from_le_bytes([chunk[2], chunk[3], chunk[4], chunk[5]]);
+
+        // The value of an instruction without an operand cannot be written as text
+        if op_type == OpType::NoValue && op_value != 0 {
+            return Err(DecodeError::UnexpectedValue { index });
+        }
+
+        let (op2_type, op2_value) = if op_code.has_second_operand() {
+            let Some((chunk, tail)) = rest.split_first_chunk::<SECOND_OPERAND_SIZE>() else {
+                return Err(truncated);
+            };
+            rest = tail;
+
+            let op2_type = op_type_from_byte(chunk[0])
+                .ok_or(DecodeError::UnknownOperandType { index, op_type: chunk[0] })?;
+            (Some(op2_type), Some(i32::from_le_bytes([chunk[1], chunk[2], chunk[3], chunk[4]])))
+        } else {
+            (None, None)
+        };
+
+        instructions.push(Instruction {
+            op_code, op_type, op_value, comment: None, source: None, extension: None, op2_type, op2_value,
+        });
+    }
+
+    Ok(instructions)
+}
+
+/// Writes the instructions as source code, one instruction per line
+pub fn disassemble(instrs: &[Instruction]) -> String {
+    instrs.iter().map(|inst| format!("{inst}\n")).collect()
+}
+
+/// Encodes the instructions, decodes them, disassembles them into text and parses that text
+/// again. Returns the parsed instructions, or the first step that did not give back the original
+/// instructions. Comments and source lines are not compared, since bytecode does not keep them.
+pub fn roundtrip(instrs: &[Instruction]) -> Result<Vec<Instruction>, RoundtripError> {
+    let decoded = decode(&encode(instrs)).map_err(RoundtripError::Decode)?;
+    let text = disassemble(&decoded);
+    let parsed = Parser::default().parse(&text).map_err(RoundtripError::Parse)?.instructions;
+
+    if parsed.len() != instrs.len() {
+        return Err(RoundtripError::LengthMismatch { expected: instrs.len(), found: parsed.len() });
+    }
+
+    for (index, (expected, found)) in instrs.iter().zip(&parsed).enumerate() {
+        let same = expected.op_code == found.op_code
+            && expected.op_type == found.op_type
+            && expected.op_value == found.op_value
+            && expected.second_operand() == found.second_operand();
+
+        if !same {
+            return Err(RoundtripError::Mismatch { index });
+        }
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OP_CODES: [OpCode; 21] = [
+        OpCode::LOAD, OpCode::STORE, OpCode::ADD, OpCode::SUB, OpCode::MULT, OpCode::DIV, OpCode::READ,
+        OpCode::WRITE, OpCode::JUMP, OpCode::JGTZ, OpCode::JZERO, OpCode::HALT, OpCode::JNEG, OpCode::JNZ,
+        OpCode::SWAP, OpCode::DUMP, OpCode::PUSH, OpCode::POP, OpCode::RAND, OpCode::CLEAR, OpCode::SET,
+    ];
+
+    const OP_TYPES: [OpType; 6] = [
+        OpType::Register, OpType::Value, OpType::ReadReg, OpType::NoValue, OpType::Indexed, OpType::Relative,
+    ];
+
+    /// Small deterministic generator, so that a failure can be reproduced
+    struct SplitMix(u64);
+
+    impl SplitMix {
+        fn next(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        }
+
+        fn below(&mut self, bound: usize) -> usize {
+            (self.next() % bound as u64) as usize
+        }
+
+        fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+            &items[self.below(items.len())]
+        }
+
+        /// Any value, biased towards the small ones and the extremes
+        fn value(&mut self) -> i32 {
+            match self.below(4) {
+                0 => *self.choose(&[0, 1, -1, i32::MIN, i32::MAX]),
+                1 => self.next() as i32,
+                _ => self.below(41) as i32 - 20,
+            }
+        }
+
+        /// Value of an argument of the given type, valid for an instruction with index `index` of
+        /// a program made of `len` instructions
+        fn operand_value(&mut self, op_code: &OpCode, op_type: &OpType, index: usize, len: usize) -> i32 {
+            match op_type {
+                OpType::NoValue => 0,
+                OpType::Relative => self.below(len + 1) as i32 - index as i32,
+                OpType::Register | OpType::Value if op_code.is_jump() => self.below(len + 1) as i32,
+                OpType::Register | OpType::ReadReg => self.value().checked_abs().unwrap_or(0),
+                OpType::Value | OpType::Indexed => self.value(),
+            }
+        }
+    }
+
+    /// Random program that the parser accepts, made of up to 20 instructions
+    fn random_program(rng: &mut SplitMix) -> Vec<Instruction> {
+        let len = rng.below(20) + 1;
+        (0..len)
+            .map(|index| {
+                let op_code = rng.choose(&OP_CODES).clone();
+                let op_types: Vec<&OpType> = OP_TYPES.iter().filter(|op_type| op_code.accepts_operand(op_type)).collect();
+                let op_type = (*rng.choose(&op_types)).clone();
+                let op_value = rng.operand_value(&op_code, &op_type, index, len);
+
+                let (op2_type, op2_value) = if op_code.has_second_operand() {
+                    let op_types: Vec<&OpType> =
+                        OP_TYPES.iter().filter(|op_type| op_code.accepts_second_operand(op_type)).collect();
+                    let op2_type = (*rng.choose(&op_types)).clone();
+                    let op2_value = rng.operand_value(&op_code, &op2_type, index, len);
+                    (Some(op2_type), Some(op2_value))
+                } else {
+                    (None, None)
+                };
+
+                Instruction {
+                    op_code, op_type, op_value, comment: None, source: None, extension: None, op2_type, op2_value,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn random_programs_survive_roundtrip() {
+        let mut rng = SplitMix(0x5eed);
+        let mut seen_types = Vec::new();
+        let mut seen_second_types = Vec::new();
+
+        for _ in 0..2000 {
+            let program = random_program(&mut rng);
+            assert!(program.iter().all(Instruction::has_valid_operand));
+
+            match roundtrip(&program) {
+                Ok(parsed) => assert_eq!(parsed.len(), program.len()),
+                Err(error) => panic!("{error}\n{}", disassemble(&program)),
+            }
+
+            for inst in &program {
+                if !seen_types.contains(&inst.op_type) {
+                    seen_types.push(inst.op_type.clone());
+                }
+                if let Some((op_type, _)) = inst.second_operand() {
+                    if !seen_second_types.contains(op_type) {
+                        seen_second_types.push(op_type.clone());
+                    }
+                }
+            }
+        }
+
+        // Every operand type, including the ones of the second argument of SET, has to be covered
+        assert!(OP_TYPES.iter().all(|op_type| seen_types.contains(op_type)));
+        let second_types: Vec<&OpType> =
+            OP_TYPES.iter().filter(|op_type| OpCode::SET.accepts_second_operand(op_type)).collect();
+        assert!(!second_types.is_empty());
+        assert!(second_types.into_iter().all(|op_type| seen_second_types.contains(op_type)));
+    }
+
+    #[test]
+    fn encoded_set_keeps_second_operand() {
+        let source = "SET 5, =42\nSET 1, *2\nSET 0 @-3\nHALT";
+        let instructions = Parser::default().parse(source).unwrap().instructions;
+
+        let bytes = encode(&instructions);
+        assert_eq!(bytes.len(), 3 * (INSTRUCTION_SIZE + SECOND_OPERAND_SIZE) + INSTRUCTION_SIZE);
+
+        let decoded = decode(&bytes).unwrap();
+        let operands: Vec<_> = decoded.iter().map(Instruction::second_operand).collect();
+        assert_eq!(operands, [
+            Some((&OpType::Value, 42)),
+            Some((&OpType::ReadReg, 2)),
+            Some((&OpType::Indexed, -3)),
+            None,
+        ]);
+        assert_eq!(roundtrip(&instructions).map(|parsed| parsed.len()), Ok(4));
+    }
+}
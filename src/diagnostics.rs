@@ -0,0 +1,206 @@
+use alloc::{format, string::{String, ToString}, vec::Vec};
+
+use crate::analysis::Warning;
+use crate::parser::{validate, ParseError, Parser};
+use crate::DEFAULT_TAB_WIDTH;
+
+/// How serious the reported problem is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The program cannot be run
+    Error,
+    /// The program can be run, but most likely does not do what was intended
+    Warning,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// Single problem found in the source, in the shape expected by editors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Line of the problem (starting from 1), `None` when it is not tied to any line
+    pub line: Option<usize>,
+    /// Column of the problem (starting from 1), with tabs expanded. It is the column of the
+    /// offending token or of the instruction in the line, and 1 when the line is not known
+    pub column: usize,
+    pub severity: Severity,
+    pub message: String,
+    /// Short identifier of the kind of the problem, for example `invalid-instruction`
+    pub code: &'static str,
+}
+
+fn error_code(error: &ParseError) -> &'static str {
+    match error {
+        ParseError::InvalidInstruction { .. } => "invalid-instruction",
+        ParseError::RepeatingLabel { .. } => "repeating-label",
+        ParseError::EmptyLabel { .. } => "empty-label",
+        ParseError::RepeatingConstant { .. } => "repeating-constant",
+        ParseError::InvalidConstant { .. } => "invalid-constant",
+        ParseError::NameCollision { .. } => "name-collision",
+        ParseError::ReservedName { .. } => "reserved-name",
+        ParseError::LabelNotFound { .. } => "label-not-found",
+        ParseError::DanglingLabelUse { .. } => "dangling-label-use",
+        ParseError::JumpOutOfRange { .. } => "jump-out-of-range",
+        ParseError::InvalidOperand { .. } => "invalid-operand",
+        ParseError::InvalidStart { .. } => "invalid-start",
+        ParseError::ImmediateOutOfRange { .. } => "immediate-out-of-range",
+    }
+}
+
+fn warning_code(warning: &Warning) -> &'static str {
+    match warning {
+        Warning::UnreachableInstruction { .. } => "unreachable-instruction",
+        Warning::UnusedLabel { .. } => "unused-label",
+        Warning::DivisionByConstantZero { .. } => "division-by-zero",
+        Warning::ConstantOverflow { .. } => "constant-overflow",
+        Warning::TrivialInfiniteLoop { .. } => "trivial-infinite-loop",
+        Warning::IgnoredAfterEnd { .. } => "ignored-after-end",
+    }
+}
+
+/// Drops the `ERROR: ` or `WARNING: ` prefix, editors show the severity on their own
+fn strip_severity(message: String) -> String {
+    match message.split_once(": ") {
+        Some(("ERROR" | "WARNING", rest)) => rest.to_string(),
+        _ => message,
+    }
+}
+
+/// Collects all of the parse errors followed by all of the warnings found in the source. Warnings
+/// that need the whole program, such as unreachable instructions, are found only when the source
+/// parses. Columns are reported with tabs [`DEFAULT_TAB_WIDTH`] columns wide
+pub fn diagnose(source: &str) -> Vec<Diagnostic> {
+    diagnose_with_tab_width(source, DEFAULT_TAB_WIDTH)
+}
+
+/// Same as [`diagnose`], but with the reported columns calculated for tabs `tab_width` columns
+/// wide
+pub fn diagnose_with_tab_width(source: &str, tab_width: usize) -> Vec<Diagnostic> {
+    // The parser keeps the line numbers of the instructions and the found warnings even when
+    // parsing fails
+    let mut parser = Parser::default();
+    parser.set_tab_width(tab_width);
+    let _ = parser.parse(source);
+
+    let errors = validate(source).err().unwrap_or_default();
+    let mut diagnostics: Vec<Diagnostic> = errors
+        .into_iter()
+        .map(|error| {
+            let line = match &error {
+                ParseError::JumpOutOfRange { index, .. } => parser.instruction_lines().get(*index).copied(),
+                _ => error.line(),
+            };
+
+            Diagnostic {
+                line,
+                column: line.and_then(|line| parser.column(line)).unwrap_or(1),
+                severity: Severity::Error,
+                code: error_code(&error),
+                message: strip_severity(error.to_string()),
+            }
+        })
+        .collect();
+
+    let warnings = parser.warnings().iter().map(|warning| {
+        let line = match warning {
+            Warning::UnreachableInstruction { line, .. }
+            | Warning::DivisionByConstantZero { line, .. }
+            | Warning::ConstantOverflow { line, .. }
+            | Warning::TrivialInfiniteLoop { line, .. }
+            | Warning::IgnoredAfterEnd { line } => Some(*line),
+            Warning::UnusedLabel { label } => parser.label_line(label),
+        };
+
+        Diagnostic {
+            line,
+            column: line.and_then(|line| parser.column(line)).unwrap_or(1),
+            severity: Severity::Warning,
+            code: warning_code(warning),
+            message: strip_severity(warning.to_string()),
+        }
+    });
+    diagnostics.extend(warnings);
+    diagnostics
+}
+
+/// Quotes the JSON string, escaping characters that are not allowed inside of it
+fn json_string(text: &str) -> String {
+    let mut json = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            '\r' => json.push_str("\\r"),
+            _ if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Converts the result of [`diagnose`] into a JSON array of
+/// `{ "line", "column", "severity", "message", "code" }` objects. `line` is `null` for problems
+/// that are not tied to any line
+pub fn diagnose_json(source: &str) -> String {
+    let objects: Vec<String> = diagnose(source)
+        .iter()
+        .map(|diagnostic| {
+            let line = diagnostic.line.map_or_else(|| "null".to_string(), |line| line.to_string());
+            format!(
+                "{{\"line\":{line},\"column\":{},\"severity\":{},\"message\":{},\"code\":{}}}",
+                diagnostic.column,
+                json_string(diagnostic.severity.as_str()),
+                json_string(&diagnostic.message),
+                json_string(diagnostic.code),
+            )
+        })
+        .collect();
+
+    format!("[{}]", objects.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_label_has_line() {
+        assert_eq!(
+            diagnose_json("\tADD\tfoo\n"),
+            r#"[{"line":1,"column":5,"severity":"error","message":"Exception in line 1. Label named `foo` not found.","code":"label-not-found"}]"#,
+        );
+
+        let diagnostics = diagnose("START main\nLOAD =1\nJUMP loop\nHALT");
+        let lines: Vec<Option<usize>> = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.code == "label-not-found")
+            .map(|diagnostic| diagnostic.line)
+            .collect();
+        assert_eq!(lines, [Some(3), Some(1)]);
+    }
+
+    #[test]
+    fn warnings_follow_errors() {
+        let source = "LOAD =99999999999\nFOO 1\nHALT\nEND\nnotes";
+        let codes: Vec<(Severity, &str)> = diagnose(source)
+            .iter()
+            .map(|diagnostic| (diagnostic.severity, diagnostic.code))
+            .collect();
+
+        assert_eq!(codes, [
+            (Severity::Error, "invalid-instruction"),
+            (Severity::Warning, "constant-overflow"),
+            (Severity::Warning, "ignored-after-end"),
+        ]);
+    }
+}
@@ -0,0 +1,160 @@
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+
+use crate::io::{VecInput, VecOutput};
+use crate::parser::{ParseError, Parser};
+use crate::ram::{RegisterData, RuntimeError, RAM};
+
+/// Number of instructions each program may execute for a single input, prevents programs that
+/// never finish from hanging the check
+pub const EQUIVALENCE_STEP_LIMIT: usize = 1_000_000;
+
+/// Result of a single run
+#[derive(Debug, PartialEq, Eq)]
+pub struct RunResult {
+    /// Values written by the program, including the ones written before a runtime error
+    pub output: Vec<RegisterData>,
+    /// Error that stopped the program, `None` when it finished normally
+    pub error: Option<RuntimeError>,
+}
+
+impl RunResult {
+    /// Returns `true` if both runs wrote the same values and either finished normally or failed
+    /// with the same kind of error. Details of the errors, such as the step they happened in, are
+    /// not compared
+    fn same_as(&self, other: &RunResult) -> bool {
+        let same_error = match (&self.error, &other.error) {
+            (Some(error), Some(other)) => {
+                core::mem::discriminant(error.root()) == core::mem::discriminant(other.root())
+            }
+            (None, None) => true,
+            _ => false,
+        };
+        same_error && self.output == other.output
+    }
+}
+
+/// Result of comparing two programs with [`check_equivalence`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum EquivalenceReport {
+    /// Both programs wrote the same values for all of the inputs
+    Equivalent { inputs_checked: usize },
+    /// Programs behaved differently for the input with index `index`
+    Diverged {
+        index: usize,
+        input: Vec<RegisterData>,
+        reference: RunResult,
+        candidate: RunResult,
+    },
+    /// The reference program could not be parsed
+    InvalidReference(ParseError),
+    /// The candidate program could not be parsed
+    InvalidCandidate(ParseError),
+}
+
+impl core::fmt::Display for EquivalenceReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EquivalenceReport::Equivalent { inputs_checked } => {
+                write!(f, "Programs are equivalent for all {inputs_checked} inputs")
+            }
+            EquivalenceReport::Diverged { index, input, reference, candidate } => {
+                writeln!(f, "Programs diverge for input number {index}: {input:?}")?;
+                writeln!(f, "Reference: {}", format_run(reference))?;
+                write!(f, "Candidate: {}", format_run(candidate))
+            }
+            EquivalenceReport::InvalidReference(error) => write!(f, "Reference program: {error}"),
+            EquivalenceReport::InvalidCandidate(error) => write!(f, "Candidate program: {error}"),
+        }
+    }
+}
+
+fn format_run(result: &RunResult) -> String {
+    match &result.error {
+        Some(error) => format!("{:?}, then {error}", result.output),
+        None => format!("{:?}", result.output),
+    }
+}
+
+/// Parses the source into a machine that is ready to be run
+fn load(source: &str) -> Result<RAM, ParseError> {
+    let output = Parser::default().parse(source)?;
+
+    let mut ram = RAM::builder().max_steps(EQUIVALENCE_STEP_LIMIT).build();
+    ram.load_instructions(output.instructions)?;
+    ram.set_entry_point(output.entry_point);
+    Ok(ram)
+}
+
+/// Runs the machine from its initial state on the given input and collects the written values
+fn run_with_input(ram: &mut RAM, input: &[RegisterData]) -> RunResult {
+    let output = VecOutput::default();
+    ram.reset();
+    ram.set_input(Box::new(VecInput::new(input)));
+    ram.set_output(Box::new(output.clone()));
+    let error = ram.run().err();
+    RunResult { output: output.values(), error }
+}
+
+/// Runs both programs on every input and compares the values they write.
+///
+/// The programs are considered to behave the same for an input when they write the same values
+/// and either both finish normally or both fail with the same kind of runtime error, for example
+/// both divide by zero. Each run is limited to [`EQUIVALENCE_STEP_LIMIT`] steps. Checking stops at
+/// the first input the programs diverge for.
+pub fn check_equivalence(reference: &str, candidate: &str, inputs: &[Vec<RegisterData>]) -> EquivalenceReport {
+    let mut reference_ram = match load(reference) {
+        Ok(ram) => ram,
+        Err(error) => return EquivalenceReport::InvalidReference(error),
+    };
+
+    let mut candidate_ram = match load(candidate) {
+        Ok(ram) => ram,
+        Err(error) => return EquivalenceReport::InvalidCandidate(error),
+    };
+
+    for (index, input) in inputs.iter().enumerate() {
+        let reference = run_with_input(&mut reference_ram, input);
+        let candidate = run_with_input(&mut candidate_ram, input);
+
+        if !reference.same_as(&candidate) {
+            return EquivalenceReport::Diverged { index, input: input.clone(), reference, candidate };
+        }
+    }
+
+    EquivalenceReport::Equivalent { inputs_checked: inputs.len() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIVIDE: &str = "READ 1\nLOAD =10\nDIV 1\nWRITE 0\nHALT";
+
+    #[test]
+    fn same_programs_are_equivalent() {
+        let inputs = [vec![1], vec![2], vec![0]];
+        assert_eq!(check_equivalence(DIVIDE, DIVIDE, &inputs), EquivalenceReport::Equivalent { inputs_checked: 3 });
+    }
+
+    #[test]
+    fn different_errors_diverge() {
+        // Divides by zero for 0, while the candidate runs out of input
+        let candidate = "READ 1\nREAD 2\nLOAD =10\nDIV 1\nWRITE 0\nHALT";
+        let report = check_equivalence(DIVIDE, candidate, &[vec![0]]);
+
+        let EquivalenceReport::Diverged { reference, candidate, .. } = report else {
+            panic!("expected the programs to diverge, got {report:?}");
+        };
+        assert_eq!(reference.error.as_ref().map(RuntimeError::root), Some(&RuntimeError::DivisionByZero));
+        let candidate_error = candidate.error.as_ref().map(RuntimeError::root);
+        assert!(matches!(candidate_error, Some(RuntimeError::UnexpectedEof { .. })));
+    }
+
+    #[test]
+    fn output_before_error_is_compared() {
+        let reference = "WRITE =1\nDIV =0";
+        let candidate = "WRITE =2\nDIV =0";
+        assert!(matches!(check_equivalence(reference, candidate, &[vec![]]), EquivalenceReport::Diverged { .. }));
+        assert!(matches!(check_equivalence(reference, reference, &[vec![]]), EquivalenceReport::Equivalent { .. }));
+    }
+}
@@ -0,0 +1,58 @@
+use crate::parser::ParseError;
+use crate::ram::RuntimeError;
+
+/// Any error that stops a program from being loaded or executed
+#[derive(Debug, PartialEq, Eq)]
+pub enum RamError {
+    /// The source could not be parsed, the program was not loaded
+    Parse(ParseError),
+    /// The program failed while being executed
+    Runtime(RuntimeError),
+}
+
+impl core::fmt::Display for RamError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // Both errors already mention the line they come from whenever it is known
+        match self {
+            RamError::Parse(error) => write!(f, "{error}"),
+            RamError::Runtime(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl core::error::Error for RamError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            RamError::Parse(error) => Some(error),
+            RamError::Runtime(error) => Some(error),
+        }
+    }
+}
+
+impl From<ParseError> for RamError {
+    fn from(error: ParseError) -> Self {
+        RamError::Parse(error)
+    }
+}
+
+impl From<RuntimeError> for RamError {
+    fn from(error: RuntimeError) -> Self {
+        RamError::Runtime(error)
+    }
+}
+
+impl RamError {
+    /// Line of the source the error comes from, if it is known
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            RamError::Parse(error) => error.line(),
+            RamError::Runtime(RuntimeError::InInstruction { line, .. }) => Some(*line),
+            RamError::Runtime(_) => None,
+        }
+    }
+
+    /// `true` when the program failed while being executed, rather than while being loaded
+    pub fn is_runtime(&self) -> bool {
+        matches!(self, RamError::Runtime(_))
+    }
+}
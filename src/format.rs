@@ -0,0 +1,197 @@
+use alloc::{format, string::{String, ToString}, vec::Vec};
+
+use crate::{source_lines, OpCode};
+use crate::parser::{attach_label_colon, code_tokens, split_comment, split_statements, ParseError, Parser};
+
+/// Smallest column the opcodes are indented to
+const MIN_OPCODE_COLUMN: usize = 4;
+
+/// Line of the source split into the parts that get aligned
+struct FormattedLine {
+    /// Labels declared in the line, with their colons
+    labels: Vec<String>,
+    /// Opcode or directive name
+    opcode: Option<String>,
+    /// Arguments of the opcode, separated with single spaces or with a comma for two arguments
+    operands: String,
+    /// Comment together with its comment character
+    comment: Option<String>,
+}
+
+impl FormattedLine {
+    fn parse(line: &str) -> Self {
+        let (code, comment) = split_comment(line);
+        let comment = comment.map(|text| {
+            let comment_char = &line[code.len()..code.len() + 1];
+            let text = text.trim();
+            if text.is_empty() { comment_char.to_string() } else { format!("{comment_char} {text}") }
+        });
+
+        let mut labels = Vec::new();
+        let mut tokens = code_tokens(code);
+        let mut opcode = None;
+        while let Some(token) = tokens.next() {
+            let mut word = token.to_string();
+            attach_label_colon(&mut word, &mut tokens);
+            if word.ends_with(':') {
+                labels.push(word);
+            } else {
+                opcode = Some(word);
+                break;
+            }
+        }
+
+        // The first two arguments of the instructions with two of them keep the comma, as in
+        // `SET 5, =3`. Only the tokens of this statement are joined
+        let has_second_operand = opcode
+            .as_deref()
+            .and_then(OpCode::from_name)
+            .is_some_and(|op_code| op_code.has_second_operand());
+        let mut operands = tokens.collect::<Vec<_>>().join(" ");
+        if has_second_operand {
+            if let Some(space) = operands.find(' ') {
+                operands.insert(space, ',');
+            }
+        }
+        Self { labels, opcode, operands, comment }
+    }
+
+    /// Text of the labels, for example `loop: end:`
+    fn labels_text(&self) -> String {
+        self.labels.join(" ")
+    }
+
+    /// Code of the line, with the opcode starting at `opcode_column` and the operands at
+    /// `operand_column`
+    fn code(&self, opcode_column: usize, operand_column: usize) -> String {
+        let mut code = self.labels_text();
+        let Some(opcode) = &self.opcode else {
+            return code;
+        };
+
+        // Labels too long for the opcode column are separated with a single space
+        code = format!("{code:<width$}", width = opcode_column.max(code.len() + 1));
+        if self.operands.is_empty() {
+            code.push_str(opcode);
+        } else {
+            code = format!("{code}{opcode:<width$}{}", self.operands, width = operand_column - opcode_column);
+        }
+        code
+    }
+}
+
+/// Reparses the source and writes it back with consistent alignment: labels at the beginning of
+/// the line, opcodes, operands and trailing comments each aligned to a common column. Comment only
+/// lines start at the beginning of the line, blank lines are kept. Statements separated with `|`
+/// are written one per line.
+///
+/// Source after the `END` directive is not parsed, so it is kept as is. Returns the first error
+/// if the source cannot be parsed.
+pub fn format_source(source: &str) -> Result<String, ParseError> {
+    Parser::default().parse(source)?;
+
+    let mut lines = Vec::new();
+    let mut remainder = Vec::new();
+    'lines: for (idx, text) in source_lines(source).enumerate() {
+        // Statements separated with `|` are written in separate lines, the comment stays with
+        // the last one
+        let statements = split_statements(text);
+        let is_single = statements.len() == 1;
+        let mut statements = statements.into_iter();
+        while let Some(statement) = statements.next() {
+            if !is_single && statement.trim().is_empty() {
+                continue;
+            }
+
+            let line = FormattedLine::parse(statement);
+            let is_end = matches!(line.opcode.as_deref(), Some("END" | "EOF"));
+            lines.push(line);
+
+            if is_end {
+                let rest = statements.collect::<Vec<_>>().join("|");
+                remainder.extend(Some(rest.trim()).filter(|rest| !rest.is_empty()).map(str::to_string));
+                remainder.extend(source_lines(source).skip(idx + 1).map(|line| line.trim_end().to_string()));
+                break 'lines;
+            }
+        }
+    }
+
+    let with_opcode = || lines.iter().filter(|line| line.opcode.is_some());
+    let opcode_column = with_opcode()
+        .map(|line| line.labels_text().len() + 1)
+        .filter(|&column| column > 1)
+        .max()
+        .unwrap_or(0)
+        .max(MIN_OPCODE_COLUMN);
+    let opcode_width = with_opcode()
+        .filter(|line| !line.operands.is_empty())
+        .filter_map(|line| line.opcode.as_ref().map(String::len))
+        .max()
+        .unwrap_or(0);
+    let operand_column = opcode_column + opcode_width + 1;
+
+    let codes: Vec<String> = lines
+        .iter()
+        .map(|line| line.code(opcode_column, operand_column))
+        .collect();
+    let comment_column = lines
+        .iter()
+        .zip(&codes)
+        .filter(|(line, code)| line.comment.is_some() && !code.is_empty())
+        .map(|(_, code)| code.len() + 1)
+        .max()
+        .unwrap_or(0);
+
+    let mut formatted = String::new();
+    for (line, code) in lines.iter().zip(codes) {
+        match &line.comment {
+            Some(comment) if code.is_empty() => formatted.push_str(comment),
+            Some(comment) => formatted.push_str(&format!("{code:<comment_column$}{comment}")),
+            None => formatted.push_str(&code),
+        }
+        formatted.push('\n');
+    }
+
+    for line in remainder {
+        formatted.push_str(&line);
+        formatted.push('\n');
+    }
+
+    Ok(formatted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_comma_between_operands() {
+        let cases = [
+            ("SET 5, =3\nHALT", "    SET 5, =3\n    HALT\n"),
+            ("SET 5 ,=3\nHALT", "    SET 5, =3\n    HALT\n"),
+            ("start: SET 1,2\nLOAD 1", "start: SET  1, 2\n       LOAD 1\n"),
+            ("SET 1 2 | HALT", "    SET 1, 2\n    HALT\n"),
+            ("SET 1, =2 | LOAD 1 | WRITE 0", "    SET   1, =2\n    LOAD  1\n    WRITE 0\n"),
+        ];
+
+        for (source, formatted) in cases {
+            assert_eq!(format_source(source).unwrap(), formatted, "source: {source:?}");
+            assert_eq!(format_source(formatted).unwrap(), formatted, "source: {source:?}");
+        }
+    }
+
+    #[test]
+    fn splits_statements() {
+        let cases = [
+            ("LOAD =1 | HALT", "    LOAD =1\n    HALT\n"),
+            ("LOAD =1 | loop: ADD =1 | JUMP loop", "      LOAD =1\nloop: ADD  =1\n      JUMP loop\n"),
+            ("READ 0 | WRITE 0 ; echo | twice\nHALT", "    READ  0\n    WRITE 0 ; echo | twice\n    HALT\n"),
+            ("HALT | END | junk\nmore", "    HALT\n    END\njunk\nmore\n"),
+        ];
+
+        for (source, formatted) in cases {
+            assert_eq!(format_source(source).unwrap(), formatted, "source: {source:?}");
+            assert_eq!(format_source(formatted).unwrap(), formatted, "source: {source:?}");
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use alloc::{format, string::{String, ToString}, vec::Vec};
+
+use crate::{Instruction, OpCode, OpType};
+
+/// Errors that can occur while importing a program from JSON
+#[derive(Debug, PartialEq, Eq)]
+pub enum ImportError {
+    /// Text is not valid JSON or does not have the expected shape, `position` is a byte offset
+    Syntax { position: usize, message: String },
+    /// Required field of the instruction number `index` is missing
+    MissingField { index: usize, field: &'static str },
+    /// Field of the instruction number `index` has a value of incorrect type
+    InvalidField { index: usize, field: String },
+    /// Instruction number `index` uses an opcode that does not exist
+    UnknownOpcode { index: usize, name: String },
+    /// Instruction number `index` uses an addressing mode that does not exist
+    UnknownMode { index: usize, mode: String },
+    /// Instruction number `index` cannot be used with its addressing mode, for example `STORE =1`
+    InvalidOperand { index: usize },
+    /// Value of the instruction number `index` does not fit in 32 bits
+    ValueOutOfRange { index: usize },
+}
+
+impl core::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ImportError::Syntax { position, message } => {
+                write!(f, "ERROR: Invalid JSON at byte {position}: {message}")
+            }
+            ImportError::MissingField { index, field } => {
+                write!(f, "ERROR: Instruction number {index} is missing the `{field}` field")
+            }
+            ImportError::InvalidField { index, field } => {
+                write!(f, "ERROR: Field `{field}` of instruction number {index} has an incorrect value")
+            }
+            ImportError::UnknownOpcode { index, name } => {
+                write!(f, "ERROR: Instruction number {index} uses `{name}`, which does not exist")
+            }
+            ImportError::UnknownMode { index, mode } => {
+                write!(f, "ERROR: Instruction number {index} uses mode `{mode}`, which does not exist")
+            }
+            ImportError::InvalidOperand { index } => {
+                write!(f, "ERROR: Instruction number {index} has an argument of incorrect type")
+            }
+            ImportError::ValueOutOfRange { index } => {
+                write!(f, "ERROR: Value of instruction number {index} does not fit in 32 bits")
+            }
+        }
+    }
+}
+
+impl core::error::Error for ImportError {}
+
+/// Values that can appear in the instruction objects
+#[derive(Debug)]
+enum JsonValue {
+    String(String),
+    Number(i64),
+    Other,
+}
+
+/// Minimal JSON reader, understands just enough to read an array of flat objects
+struct JsonReader<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> JsonReader<'a> {
+    fn error(&self, message: &str) -> ImportError {
+        ImportError::Syntax { position: self.position, message: message.to_string() }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes `c` if it is the next character, ignoring whitespace before it
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(c) {
+            self.position += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ImportError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{c}`")))
+        }
+    }
+
+    fn read_string(&mut self) -> Result<String, ImportError> {
+        self.expect('"')?;
+
+        let mut string = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.position += offset + 1;
+                    return Ok(string);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    string.push(escaped);
+                }
+                _ => string.push(c),
+            }
+        }
+
+        Err(self.error("unterminated string"))
+    }
+
+    fn read_value(&mut self) -> Result<JsonValue, ImportError> {
+        self.skip_whitespace();
+        let rest = self.rest();
+
+        if rest.starts_with('"') {
+            return self.read_string().map(JsonValue::String);
+        }
+
+        for literal in ["true", "false", "null"] {
+            if rest.starts_with(literal) {
+                self.position += literal.len();
+                return Ok(JsonValue::Other);
+            }
+        }
+
+        let length = rest
+            .find(|c: char| !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')))
+            .unwrap_or(rest.len());
+        if length == 0 {
+            return Err(self.error("expected a value"));
+        }
+
+        let number = &rest[..length];
+        self.position += length;
+        match number.parse::<i64>() {
+            Ok(value) => Ok(JsonValue::Number(value)),
+            // Fractions are valid JSON, but never valid instruction values
+            Err(_) if number.parse::<f64>().is_ok() => Ok(JsonValue::Other),
+            Err(_) => Err(self.error("invalid number")),
+        }
+    }
+
+    /// Reads an object made of string keys and plain values
+    fn read_object(&mut self) -> Result<Vec<(String, JsonValue)>, ImportError> {
+        self.expect('{')?;
+
+        let mut fields = Vec::new();
+        if self.eat('}') {
+            return Ok(fields);
+        }
+
+        loop {
+            let key = self.read_string()?;
+            self.expect(':')?;
+            let value = self.read_value()?;
+            fields.push((key, value));
+
+            if self.eat('}') {
+                return Ok(fields);
+            }
+            self.expect(',')?;
+        }
+    }
+}
+
+/// Addressing mode of the instruction number `index` with the given name
+fn op_type_from_mode(index: usize, mode: &str) -> Result<OpType, ImportError> {
+    let op_type = match mode {
+        "register" => OpType::Register,
+        "value" => OpType::Value,
+        "indirect" => OpType::ReadReg,
+        "indexed" => OpType::Indexed,
+        "relative" => OpType::Relative,
+        "none" => OpType::NoValue,
+        _ => return Err(ImportError::UnknownMode { index, mode: mode.to_string() }),
+    };
+    Ok(op_type)
+}
+
+/// Converts a single instruction object into an instruction
+fn instruction_from_fields(index: usize, fields: Vec<(String, JsonValue)>) -> Result<Instruction, ImportError> {
+    let mut op = None;
+    let mut mode = None;
+    let mut value = None;
+    let mut mode2 = None;
+    let mut value2 = None;
+
+    for (key, field) in fields {
+        match (key.as_str(), field) {
+            ("op", JsonValue::String(name)) => op = Some(name),
+            ("mode", JsonValue::String(name)) => mode = Some(name),
+            ("value", JsonValue::Number(number)) => value = Some(number),
+            ("mode2", JsonValue::String(name)) => mode2 = Some(name),
+            ("value2", JsonValue::Number(number)) => value2 = Some(number),
+            ("op" | "mode" | "value" | "mode2" | "value2", _) => return Err(ImportError::InvalidField { index, field: key }),
+            // Unknown fields are left for other tools
+            _ => {}
+        }
+    }
+
+    let op = op.ok_or(ImportError::MissingField { index, field: "op" })?;
+    let op_code = OpCode::from_name(&op).ok_or(ImportError::UnknownOpcode { index, name: op })?;
+
+    let op_type = op_type_from_mode(index, mode.as_deref().unwrap_or("none"))?;
+
+    if !op_code.accepts_operand(&op_type) {
+        return Err(ImportError::InvalidOperand { index });
+    }
+
+    let op_value = match (&op_type, value) {
+        (OpType::NoValue, _) => 0,
+        (_, None) => return Err(ImportError::MissingField { index, field: "value" }),
+        (_, Some(value)) => i32::try_from(value).map_err(|_| ImportError::ValueOutOfRange { index })?,
+    };
+
+    let (op2_type, op2_value) = match value2 {
+        _ if !op_code.has_second_operand() => (None, None),
+        None => return Err(ImportError::MissingField { index, field: "value2" }),
+        Some(value) => {
+            let op2_type = op_type_from_mode(index, mode2.as_deref().unwrap_or("value"))?;
+            if !op_code.accepts_second_operand(&op2_type) {
+                return Err(ImportError::InvalidOperand { index });
+            }
+            let value = i32::try_from(value).map_err(|_| ImportError::ValueOutOfRange { index })?;
+            (Some(op2_type), Some(value))
+        }
+    };
+
+    Ok(Instruction {
+        op_code, op_type, op_value, comment: None, source: None, extension: None, op2_type, op2_value,
+    })
+}
+
+/// Imports a program from a JSON array of instruction objects, for example
+/// `[{"op": "ADD", "mode": "value", "value": 1}, {"op": "HALT"}]`.
+///
+/// `mode` is one of `register` (`ADD 1`), `value` (`ADD =1`), `indirect` (`ADD *1`), `indexed`
+/// (`ADD @1`), `relative` (`JUMP ~-1`) or `none` (`HALT`), which is the default. `value` is
+/// required for every mode except `none`. Jump targets are instruction indices, since labels do
+/// not exist in this format. Every instruction is checked to accept its addressing mode.
+/// Instructions with a second argument take it from `mode2` (`value` by default) and `value2`,
+/// for example `{"op": "SET", "mode": "register", "value": 5, "value2": 42}`.
+pub fn from_json(s: &str) -> Result<Vec<Instruction>, ImportError> {
+    let mut reader = JsonReader { text: s, position: 0 };
+    let mut instructions = Vec::new();
+
+    reader.expect('[')?;
+    if !reader.eat(']') {
+        loop {
+            let fields = reader.read_object()?;
+            instructions.push(instruction_from_fields(instructions.len(), fields)?);
+
+            if reader.eat(']') {
+                break;
+            }
+            reader.expect(',')?;
+        }
+    }
+
+    reader.skip_whitespace();
+    if !reader.rest().is_empty() {
+        return Err(reader.error("unexpected text after the array"));
+    }
+
+    Ok(instructions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports_instructions() {
+        let json = r#"[
+            {"op": "READ", "mode": "register", "value": 1},
+            {"op": "LOAD", "mode": "register", "value": 1},
+            {"op": "MULT", "mode": "value", "value": -2},
+            {"op": "WRITE", "mode": "register", "value": 0},
+            {"op": "HALT"}
+        ]"#;
+        let instructions: Vec<(OpCode, OpType, i32)> = from_json(json)
+            .unwrap()
+            .into_iter()
+            .map(|inst| (inst.op_code, inst.op_type, inst.op_value))
+            .collect();
+
+        assert_eq!(instructions, [
+            (OpCode::READ, OpType::Register, 1),
+            (OpCode::LOAD, OpType::Register, 1),
+            (OpCode::MULT, OpType::Value, -2),
+            (OpCode::WRITE, OpType::Register, 0),
+            (OpCode::HALT, OpType::NoValue, 0),
+        ]);
+    }
+
+    #[test]
+    fn reports_invalid_instructions() {
+        let cases = [
+            (r#"[{"op": "HALT"}, {"op": "NOPE"}]"#, ImportError::UnknownOpcode { index: 1, name: "NOPE".into() }),
+            (r#"[{"op": "ADD", "mode": "sideways", "value": 1}]"#, ImportError::UnknownMode { index: 0, mode: "sideways".into() }),
+            (r#"[{"op": "ADD", "mode": "value"}]"#, ImportError::MissingField { index: 0, field: "value" }),
+            (r#"[{"op": "STORE", "mode": "value", "value": 1}]"#, ImportError::InvalidOperand { index: 0 }),
+            (r#"[{"op": "LOAD", "mode": "value", "value": 4294967296}]"#, ImportError::ValueOutOfRange { index: 0 }),
+        ];
+
+        for (json, error) in cases {
+            assert_eq!(from_json(json).unwrap_err(), error, "json: {json}");
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use alloc::{boxed::Box, collections::VecDeque, format, rc::Rc, string::{String, ToString}, vec::Vec};
+use core::cell::RefCell;
+
+use crate::parse_integer;
+use crate::ram::RegisterData;
+
+/// Way in which values written by the `WRITE` instruction are formatted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Decimal number, for example `42`
+    #[default]
+    Decimal,
+    /// Hexadecimal number, for example `0x2a`
+    Hex,
+    /// Unicode character, for example `*`. Invalid code points are written as `�`
+    Char,
+}
+
+impl OutputFormat {
+    /// Formats the value according to the output format
+    pub fn format(&self, value: RegisterData) -> String {
+        match self {
+            OutputFormat::Decimal => value.to_string(),
+            OutputFormat::Hex if value < 0 => format!("-{:#x}", value.unsigned_abs()),
+            OutputFormat::Hex => format!("{value:#x}"),
+            OutputFormat::Char => {
+                let c = u32::try_from(value).ok().and_then(char::from_u32);
+                c.unwrap_or(char::REPLACEMENT_CHARACTER).to_string()
+            }
+        }
+    }
+}
+
+/// What is written between the values written by the `WRITE` instruction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Separator {
+    /// Every value is written in its own line
+    #[default]
+    Newline,
+    /// Values are written in a single line separated with spaces. The line ends when the machine
+    /// finishes or writes text that is not a value
+    Space,
+}
+
+/// Destination of the values written by the `WRITE` instruction
+pub trait OutputSink: core::fmt::Debug {
+    /// Writes a single value, `formatted` is the value formatted with the current [`OutputFormat`].
+    /// The value is not followed by anything, the machine calls [`OutputSink::write_separator`]
+    /// or [`OutputSink::flush_line`] after it
+    fn write_value(&mut self, value: RegisterData, formatted: &str);
+
+    /// Writes the separator between two values of the same line. Ignored by default
+    fn write_separator(&mut self, _separator: Separator) {}
+
+    /// Ends the current line of values. Ignored by default
+    fn flush_line(&mut self) {}
+
+    /// Writes a line of text that is not a value, such as the register dump of the `DUMP`
+    /// instruction. Ignored by default
+    fn write_text(&mut self, _text: &str) {}
+
+    /// Values written so far, for sinks that keep them in memory. `None` by default
+    fn captured_values(&self) -> Option<Vec<RegisterData>> {
+        None
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for Box<dyn OutputSink> {
+    fn default() -> Self {
+        Box::new(StdOutput)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Default for Box<dyn OutputSink> {
+    fn default() -> Self {
+        Box::new(VecOutput::default())
+    }
+}
+
+/// Writes values to the standard output, one value per line
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub struct StdOutput;
+
+#[cfg(feature = "std")]
+impl OutputSink for StdOutput {
+    fn write_value(&mut self, _value: RegisterData, formatted: &str) {
+        print!("{formatted}");
+    }
+
+    fn write_separator(&mut self, separator: Separator) {
+        match separator {
+            Separator::Newline => println!(),
+            Separator::Space => print!(" "),
+        }
+    }
+
+    fn flush_line(&mut self) {
+        println!();
+    }
+
+    fn write_text(&mut self, text: &str) {
+        println!("{text}");
+    }
+}
+
+/// Collects written values in memory.
+///
+/// Clones share the same buffer, so a clone can be kept to read the values after the original was
+/// given to the machine.
+#[derive(Debug, Default, Clone)]
+pub struct VecOutput {
+    values: Rc<RefCell<Vec<RegisterData>>>,
+}
+
+impl VecOutput {
+    /// Values written so far
+    pub fn values(&self) -> Vec<RegisterData> {
+        self.values.borrow().clone()
+    }
+}
+
+impl OutputSink for VecOutput {
+    fn write_value(&mut self, value: RegisterData, _formatted: &str) {
+        self.values.borrow_mut().push(value);
+    }
+
+    fn captured_values(&self) -> Option<Vec<RegisterData>> {
+        Some(self.values())
+    }
+}
+
+/// Source of the values read by the `READ` instruction
+pub trait InputSource: core::fmt::Debug {
+    /// Reads the next value. Returns `None` at the end of the input and `Some(Err(text))` when
+    /// the data is not a valid integer literal
+    fn read_value(&mut self) -> Option<Result<RegisterData, String>>;
+}
+
+#[cfg(feature = "std")]
+impl Default for Box<dyn InputSource> {
+    fn default() -> Self {
+        Box::new(StdInput)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Default for Box<dyn InputSource> {
+    fn default() -> Self {
+        Box::new(VecInput::default())
+    }
+}
+
+/// Reads values from the standard input, one value per line
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub struct StdInput;
+
+#[cfg(feature = "std")]
+impl InputSource for StdInput {
+    fn read_value(&mut self) -> Option<Result<RegisterData, String>> {
+        let mut buffer = String::new();
+        match std::io::stdin().read_line(&mut buffer) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => {
+                let text = buffer.trim();
+                Some(parse_integer(text).ok_or_else(|| text.to_string()))
+            }
+        }
+    }
+}
+
+/// Values provided up front, so that the execution never waits for the input
+#[derive(Debug, Default, Clone)]
+pub struct VecInput {
+    values: VecDeque<Result<RegisterData, String>>,
+}
+
+impl VecInput {
+    pub fn new(values: &[RegisterData]) -> Self {
+        Self { values: values.iter().copied().map(Ok).collect() }
+    }
+
+    /// Parses whitespace separated integer literals. Invalid literals are kept, so that, like
+    /// with [`StdInput`], they are reported by the `READ` instruction that reads them. See
+    /// [`VecInput::first_invalid`] to reject them up front
+    pub fn from_text(text: &str) -> Self {
+        let values = text
+            .split_whitespace()
+            .map(|word| parse_integer(word).ok_or_else(|| word.to_string()))
+            .collect();
+
+        Self { values }
+    }
+
+    /// First of the remaining values that is not a valid integer literal
+    pub fn first_invalid(&self) -> Option<&str> {
+        self.values.iter().find_map(|value| value.as_ref().err().map(String::as_str))
+    }
+}
+
+impl InputSource for VecInput {
+    fn read_value(&mut self) -> Option<Result<RegisterData, String>> {
+        self.values.pop_front()
+    }
+}
@@ -1,10 +1,96 @@
+#![allow(non_snake_case)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 pub mod ram;
 pub mod parser;
 pub mod new_parser;
+pub mod analysis;
+pub mod assembler;
+pub mod io;
+pub mod trace;
+pub mod equivalence;
+#[cfg(feature = "std")]
+pub mod preprocess;
+pub mod diagnostics;
+pub mod timeline;
+pub mod import;
+pub mod bytecode;
+pub mod format;
+pub mod run;
+pub mod error;
+#[cfg(feature = "ui")]
 pub mod ui;
 
+use alloc::{format, string::{String, ToString}};
+
+/// Characters that start a comment, everything after them until the end of the line is ignored
+pub const COMMENT_CHARS: &[char] = &[';', '#'];
+
+/// Lines of the source without their line endings. Unlike [`str::lines`], a carriage return is
+/// removed from the last line as well, so that `HALT\r` at the end of a file with Windows line
+/// endings is `HALT`
+pub fn source_lines(source: &str) -> impl Iterator<Item = &str> {
+    source.lines().map(|line| line.strip_suffix('\r').unwrap_or(line))
+}
+
+/// Number of columns a tab advances to when no other width is given
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+/// Column (starting from 1) of the byte at `index` in the line, as shown by an editor. Tabs move
+/// the column to the next multiple of `tab_width`, so that `\tADD` has `ADD` at column 5 when
+/// tabs are 4 columns wide
+pub fn display_column(line: &str, index: usize, tab_width: usize) -> usize {
+    let tab_width = tab_width.max(1);
+    let column = line[..index].chars().fold(0, |column, c| match c {
+        '\t' => (column / tab_width + 1) * tab_width,
+        _ => column + 1,
+    });
+    column + 1
+}
+
+/// Parses an integer literal used both in the source code and in the input data.
+///
+/// Besides regular decimal numbers (with an optional `-` or `+` sign), hexadecimal (`0x10`),
+/// octal (`0o20`) and binary (`0b10000`) literals are supported. Digits can be separated with
+/// underscores (`1_000`).
+pub fn parse_integer(text: &str) -> Option<i32> {
+    parse_integer_as(text)
+}
+
+/// Parses an integer literal in the same way as [`parse_integer`] into any integer type. Returns
+/// `None` when the literal does not fit in the type
+pub fn parse_integer_as<T: TryFrom<i128>>(text: &str) -> Option<T> {
+    parse_wide_integer(text).and_then(|value| T::try_from(value).ok())
+}
+
+/// Parses an integer literal in the same way as [`parse_integer`], but without limiting it to 32
+/// bits. Used to tell apart literals that are too big from text that is not a number at all
+pub fn parse_wide_integer(text: &str) -> Option<i128> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x" | "0X") => (16, &digits[2..]),
+        Some("0o" | "0O") => (8, &digits[2..]),
+        Some("0b" | "0B") => (2, &digits[2..]),
+        _ => (10, digits),
+    };
+
+    // Prevents labels such as `_loop` from being treated as numbers
+    if !digits.starts_with(|c: char| c.is_digit(radix)) {
+        return None;
+    }
+
+    let value = i128::from_str_radix(&digits.replace('_', ""), radix).ok()?;
+    Some(if negative { -value } else { value })
+}
+
 /// Random Access Machine Opcodes
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum OpCode {
     /// Loads data from specified register to the adder register
     LOAD = 0,
@@ -18,11 +104,11 @@ pub enum OpCode {
     MULT = 4,
     /// Divide value of the adder register with value or data in specified register
     DIV = 5,
-    /// Read data from input memory (here memory is stdin) and load it into specified register
+    /// Read data from input memory (stdin by default) and load it into specified register
     READ = 6,
-    /// Write value or data from specified register to output memory (here memory is stdout)
+    /// Write value or data from specified register to output memory (stdout by default)
     WRITE = 7,
-    /// Jump to label (or value)
+    /// Jump to label (or value), `JUMP *1` jumps to the instruction index stored in register 1
     JUMP = 8,
     /// Jump to label (or value) if value under the adder register is greater than zero
     JGTZ = 9,
@@ -30,10 +116,108 @@ pub enum OpCode {
     JZERO = 10,
     /// End the code execution
     HALT = 11,
+    /// Jump to label (or value) if value under the adder register is less than zero
+    JNEG = 12,
+    /// Jump to label (or value) if value under the adder register is not zero
+    JNZ = 13,
+    /// Exchange data of the adder register with data in specified register
+    SWAP = 14,
+    /// Write all of the non-zero registers to output memory, meant for debugging
+    DUMP = 15,
+    /// Push data of the adder register onto the stack
+    PUSH = 16,
+    /// Pop the value from the top of the stack into the adder register
+    POP = 17,
+    /// Instruction registered as an extension of the machine with
+    /// [`RAM::register_extension`](crate::ram::RAM::register_extension). Its mnemonic is kept in
+    /// the instruction, see [`Instruction::extension`]
+    EXT = 18,
+    /// Load a pseudorandom value from 0 up to (but not including) the value or data from the
+    /// specified register into the adder register. The values depend only on the seed set with
+    /// [`RAM::set_seed`](crate::ram::RAM::set_seed)
+    RAND = 19,
+    /// Set all of the registers to zero. The adder register is cleared as well, unless
+    /// [`RAM::set_clear_keeps_adder`](crate::ram::RAM::set_clear_keeps_adder) is set
+    CLEAR = 20,
+    /// Write the value or data from the register given as the second argument directly into the
+    /// specified register, without going through the adder register. `SET 5, =42` is the same as
+    /// `LOAD =42` followed by `STORE 5`, except that the adder is left as it is. `SET 5, 3` copies
+    /// register 3 into register 5
+    SET = 21,
+}
+
+impl OpCode {
+    /// Finds the opcode by its name used in the source code, for example `"ADD"`
+    pub fn from_name(name: &str) -> Option<OpCode> {
+        let op_code = match name {
+            "LOAD"  => OpCode::LOAD,
+            "STORE" => OpCode::STORE,
+            "ADD"   => OpCode::ADD,
+            "SUB"   => OpCode::SUB,
+            "MULT"  => OpCode::MULT,
+            "DIV"   => OpCode::DIV,
+            "READ"  => OpCode::READ,
+            "WRITE" => OpCode::WRITE,
+            "JUMP"  => OpCode::JUMP,
+            "JGTZ"  => OpCode::JGTZ,
+            "JZERO" => OpCode::JZERO,
+            "HALT"  => OpCode::HALT,
+            "JNEG"  => OpCode::JNEG,
+            "JNZ"   => OpCode::JNZ,
+            "SWAP"  => OpCode::SWAP,
+            "DUMP"  => OpCode::DUMP,
+            "PUSH"  => OpCode::PUSH,
+            "POP"   => OpCode::POP,
+            "RAND"  => OpCode::RAND,
+            "CLEAR" => OpCode::CLEAR,
+            "SET"   => OpCode::SET,
+            _ => return None,
+        };
+        Some(op_code)
+    }
+
+    /// Returns `true` for instructions that use their argument as an instruction index
+    pub fn is_jump(&self) -> bool {
+        matches!(self, OpCode::JUMP | OpCode::JGTZ | OpCode::JZERO | OpCode::JNEG | OpCode::JNZ)
+    }
+
+    /// Returns `true` for instructions that take a second argument, for example `SET 5, =42`
+    pub fn has_second_operand(&self) -> bool {
+        matches!(self, OpCode::SET)
+    }
+
+    /// Returns `true` if the instruction can be used with the given type of the second argument.
+    /// Always `false` for instructions with a single argument
+    pub fn accepts_second_operand(&self, op_type: &OpType) -> bool {
+        match self {
+            // `SET 5, 3` copies register 3 into register 5
+            OpCode::SET => matches!(op_type, OpType::Value | OpType::Register | OpType::ReadReg | OpType::Indexed),
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the instruction can be used with the given type of argument
+    pub fn accepts_operand(&self, op_type: &OpType) -> bool {
+        if *op_type == OpType::Relative {
+            return self.is_jump() || *self == OpCode::EXT;
+        }
+
+        match self {
+            OpCode::HALT | OpCode::DUMP | OpCode::PUSH | OpCode::POP | OpCode::CLEAR => {
+                matches!(op_type, OpType::NoValue)
+            }
+            OpCode::STORE | OpCode::READ | OpCode::SWAP | OpCode::SET => {
+                matches!(op_type, OpType::Register | OpType::ReadReg | OpType::Indexed)
+            }
+            // Extensions interpret their argument on their own
+            OpCode::EXT => true,
+            _ => !matches!(op_type, OpType::NoValue),
+        }
+    }
 }
 
 /// Type of the operand
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum OpType {
     /// Use the register
     ///
@@ -51,6 +235,14 @@ pub enum OpType {
     ///
     /// Example: `HALT`
     NoValue = 3,
+    /// Use the register whose index is the value of the adder plus the value
+    ///
+    /// Example: `ADD @1`
+    Indexed = 4,
+    /// Jump by the value relative to the jump instruction itself, only for jumps
+    ///
+    /// Example: `JUMP ~-3`
+    Relative = 5,
 }
 
 // enum OpValue {
@@ -66,26 +258,247 @@ pub enum OpType {
 ///
 /// For example `ADD =12` translates to: 
 /// ```rust
-/// Instruction {
-///     op_code: OpCode::ADD,
-///     op_type: OpType::Value,
-///     op_value: 12,
-/// };
+/// use RAMulator::{parser::Parser, OpCode, OpType};
+///
+/// let parsed = Parser::default().parse("ADD =12").unwrap();
+/// let inst = &parsed.instructions[0];
+/// assert_eq!(inst.op_code(), &OpCode::ADD);
+/// assert_eq!(inst.op_type(), &OpType::Value);
+/// assert_eq!(inst.op_value(), 12);
+/// assert_eq!(inst.comment(), None);
+/// assert_eq!(inst.second_operand(), None);
 /// ```
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Instruction {
     op_code: OpCode,
     op_type: OpType,
     op_value: i32,
+    /// Comment that followed the instruction in the source, for example `increment` in
+    /// `ADD =1 ; increment`
+    comment: Option<String>,
+    /// Line of the source the instruction was parsed from, `None` when it was not parsed from text
+    source: Option<SourceLine>,
+    /// Mnemonic of the extension instruction, set only for [`OpCode::EXT`]
+    extension: Option<String>,
+    /// Type of the second argument, set only for instructions that take one (see
+    /// [`OpCode::has_second_operand`]), for example [`OpType::Value`] in `SET 5, =42`
+    op2_type: Option<OpType>,
+    /// Value of the second argument, set together with `op2_type`, for example 42 in `SET 5, =42`
+    op2_value: Option<i32>,
+}
+
+/// Line of the source code together with its number
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    /// Line number, starting from 1
+    pub line: usize,
+    /// Text of the line, without the surrounding whitespace. For lines made of several `|`
+    /// separated statements it is the text of the statement
+    pub text: String,
+}
+
+impl Instruction {
+    /// Opcode of the instruction, [`OpCode::EXT`] for the extension instructions
+    pub fn op_code(&self) -> &OpCode {
+        &self.op_code
+    }
+
+    /// Type of the argument, [`OpType::NoValue`] for instructions without one
+    pub fn op_type(&self) -> &OpType {
+        &self.op_type
+    }
+
+    /// Value of the argument, for example 12 in `ADD =12`. Labels are already resolved to the
+    /// instruction indices they point to
+    pub fn op_value(&self) -> i32 {
+        self.op_value
+    }
+
+    /// Trailing comment of the instruction, without the comment character
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    /// Line of the source the instruction was parsed from
+    pub fn source(&self) -> Option<&SourceLine> {
+        self.source.as_ref()
+    }
+
+    /// Mnemonic of the extension instruction, `None` for the built-in instructions
+    pub fn extension(&self) -> Option<&str> {
+        self.extension.as_deref()
+    }
+
+    /// Type and value of the second argument, `None` for instructions that take a single argument
+    pub fn second_operand(&self) -> Option<(&OpType, i32)> {
+        self.op2_type.as_ref().zip(self.op2_value)
+    }
+
+    /// Name of the instruction as written in the source, for example `ADD`
+    pub fn mnemonic(&self) -> String {
+        match &self.extension {
+            Some(name) => name.clone(),
+            None => format!("{:?}", self.op_code),
+        }
+    }
+
+    /// Returns `true` if the type of the argument matches the instruction and register indices
+    /// are not negative
+    pub fn has_valid_operand(&self) -> bool {
+        if !self.op_code.accepts_operand(&self.op_type) {
+            return false;
+        }
+
+        if !self.has_valid_second_operand() {
+            return false;
+        }
+
+        // Jumps use their argument as an instruction index, which is checked separately
+        let is_register = matches!(self.op_type, OpType::Register | OpType::ReadReg);
+        self.op_code.is_jump() || !is_register || self.op_value >= 0
+    }
+
+    /// Returns `true` if the instruction has the second argument exactly when it takes one, with
+    /// a type it accepts and without a negative register index
+    pub fn has_valid_second_operand(&self) -> bool {
+        match (self.op_code.has_second_operand(), self.second_operand()) {
+            (true, Some((op_type, value))) => {
+                let is_register = matches!(op_type, OpType::Register | OpType::ReadReg);
+                self.op_code.accepts_second_operand(op_type) && (!is_register || value >= 0)
+            }
+            (false, None) => self.op2_type.is_none() && self.op2_value.is_none(),
+            _ => false,
+        }
+    }
+
+    /// Text of the instruction argument with its type sign, for example `=12`. Empty for
+    /// instructions without an argument. The second argument follows after a comma, for example
+    /// `5, =42`
+    pub fn operand_text(&self) -> String {
+        let text = with_type_sign(&self.op_type, self.operand_value_text());
+        match self.second_operand() {
+            Some((op_type, value)) => format!("{text}, {}", with_type_sign(op_type, value.to_string())),
+            None => text,
+        }
+    }
+
+    /// Describes how the instruction uses its argument
+    pub fn operand_role(&self) -> OperandRole {
+        match self.op_type {
+            OpType::NoValue => OperandRole::None,
+            // `JUMP *1` reads the target from register 1
+            OpType::ReadReg => OperandRole::Register,
+            // The value is an offset from the adder, so it is written as signed even for jumps
+            OpType::Indexed => OperandRole::Register,
+            OpType::Relative => OperandRole::JumpOffset,
+            OpType::Register | OpType::Value if self.op_code.is_jump() => OperandRole::JumpTarget,
+            OpType::Register => OperandRole::Register,
+            OpType::Value => OperandRole::Data,
+        }
+    }
+
+    /// Text of the argument without its type sign. Instruction indices are unsigned, so jump
+    /// targets are written as unsigned numbers, while data is written as signed
+    fn operand_value_text(&self) -> String {
+        match self.operand_role() {
+            OperandRole::JumpTarget => (self.op_value as u32).to_string(),
+            _ => self.op_value.to_string(),
+        }
+    }
+}
+
+/// Writes the argument with the sign of its type, for example `=12` or `*3`
+fn with_type_sign(op_type: &OpType, value: String) -> String {
+    match op_type {
+        OpType::Register => value,
+        OpType::Value => format!("={value}"),
+        OpType::ReadReg => format!("*{value}"),
+        OpType::Indexed => format!("@{value}"),
+        OpType::Relative => format!("~{value}"),
+        OpType::NoValue => String::new(),
+    }
+}
+
+/// Way in which an instruction uses its argument
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandRole {
+    /// Instruction has no argument
+    None,
+    /// Index of the instruction to jump to, for example `JUMP 3`
+    JumpTarget,
+    /// Distance from the jump instruction to the instruction to jump to, for example `JUMP ~-3`
+    JumpOffset,
+    /// Value used in the computation, for example `ADD =-5`
+    Data,
+    /// Index of a register, for example `ADD 1` or `ADD *1`
+    Register,
 }
 
-impl std::fmt::Display for Instruction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self.op_type {
-            OpType::Register => write!(f, "{:?}\t {}", self.op_code, self.op_value),
-            OpType::Value => write!(f, "{:?}\t={}", self.op_code, self.op_value),
-            OpType::ReadReg => write!(f, "{:?}\t*{}", self.op_code, self.op_value),
-            OpType::NoValue => write!(f, "{:?}", self.op_code),
+            OpType::Register => write!(f, "{}\t {}", self.mnemonic(), self.operand_value_text()),
+            OpType::Value => write!(f, "{}\t={}", self.mnemonic(), self.operand_value_text()),
+            OpType::ReadReg => write!(f, "{}\t*{}", self.mnemonic(), self.operand_value_text()),
+            OpType::Indexed => write!(f, "{}\t@{}", self.mnemonic(), self.operand_value_text()),
+            OpType::Relative => write!(f, "{}\t~{}", self.mnemonic(), self.operand_value_text()),
+            OpType::NoValue => write!(f, "{}", self.mnemonic()),
+        }?;
+
+        if let Some((op_type, value)) = self.second_operand() {
+            write!(f, ", {}", with_type_sign(op_type, value.to_string()))?;
+        }
+
+        match &self.comment {
+            Some(comment) => write!(f, "\t; {comment}"),
+            None => Ok(()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn source_lines_strip_carriage_returns() {
+        let lines: Vec<&str> = source_lines("LOAD =1\r\nWRITE 0\r\nHALT\r").collect();
+        assert_eq!(lines, ["LOAD =1", "WRITE 0", "HALT"]);
+    }
+
+    #[test]
+    fn parses_crlf_source() {
+        let parsed = Parser::default().parse("LOAD =1\r\nWRITE 0\r\nHALT").unwrap();
+
+        let instructions: Vec<(&OpCode, &OpType, i32)> = parsed
+            .instructions
+            .iter()
+            .map(|inst| (inst.op_code(), inst.op_type(), inst.op_value()))
+            .collect();
+        assert_eq!(instructions, [
+            (&OpCode::LOAD, &OpType::Value, 1),
+            (&OpCode::WRITE, &OpType::Register, 0),
+            (&OpCode::HALT, &OpType::NoValue, 0),
+        ]);
+        assert_eq!(parsed.source_lines, [1, 2, 3]);
+
+        let texts: Vec<&str> = parsed
+            .instructions
+            .iter()
+            .filter_map(|inst| inst.source().map(|source| source.text.as_str()))
+            .collect();
+        assert_eq!(texts, ["LOAD =1", "WRITE 0", "HALT"]);
+    }
+
+    #[test]
+    fn runs_crlf_source() {
+        // The last line ends with a carriage return, but no line feed
+        let source = "LOAD =3\r\nloop:\r\nWRITE 0 ; current value\r\nSUB =1 | JGTZ loop\r\nHALT\r";
+        let parsed = Parser::default().parse(source).unwrap();
+        assert_eq!(parsed.instructions[1].comment(), Some("current value"));
+
+        let report = crate::run::run_checked(source, &[]).unwrap();
+        assert_eq!((report.output, report.error), (vec![3, 2, 1], None));
+    }
+}
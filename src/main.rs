@@ -1,35 +1,295 @@
-use RAMulator::{parser::Parser, ram::RAM, new_parser::NewParser, ui::run_app};
+use std::path::Path;
+use std::process::ExitCode;
 
-fn main() {
-    let _ = run_app();
+use RAMulator::{assembler, io::{VecInput, VecOutput}, parser::{self, ParseError, Parser}, ram::RAM, ui::run_app};
+use RAMulator::diagnostics::diagnose_json;
+use RAMulator::error::RamError;
+use RAMulator::import::from_json;
+use RAMulator::preprocess::{preprocess_with_locations, Preprocessed};
 
-    // let code = std::fs::read_to_string("ram/add_numbers.ram").unwrap();
-    // let code = std::fs::read_to_string("ram/example-fucked.ram").unwrap();
-    // let code = std::fs::read_to_string("ram/example.ram").unwrap();
-    // let code = std::fs::read_to_string("ram/sequence_sum.ram").unwrap();
-    // let code = std::fs::read_to_string("ram/slide.ram").unwrap();
-    let code = std::fs::read_to_string("ram/testing.ram").unwrap();
+mod repl;
 
-    let mut parser = Parser::default();
+/// Exit code used when the program finished with a runtime error
+const EXIT_RUNTIME_ERROR: u8 = 1;
+/// Exit code used on bad command line usage or when the program could not be loaded
+const EXIT_USAGE_ERROR: u8 = 2;
+
+/// Exit code of the failed program, runtime errors are told apart from programs that could not be
+/// loaded
+fn exit_code(error: &RamError) -> ExitCode {
+    if error.is_runtime() {
+        ExitCode::from(EXIT_RUNTIME_ERROR)
+    } else {
+        ExitCode::from(EXIT_USAGE_ERROR)
+    }
+}
+
+fn print_usage(program: &str) {
+    eprintln!("Usage: {program} [command]");
+    eprintln!();
+    eprintln!("Commands:");
+    eprintln!("    gui                  Launch the graphical interface (default)");
+    eprintln!("    run [--json] <program.ram> [input.txt]");
+    eprintln!("                         Execute the program writing to stdout. Input is read from");
+    eprintln!("                         the given file up front or from stdin. Programs ending");
+    eprintln!("                         with .json are imported from the JSON format. With --json");
+    eprintln!("                         the final state is printed as JSON for autograders");
+    eprintln!("    debug <program.ram>  Step through the program in an interactive debugger");
+    eprintln!("    check <program.ram> [--json]");
+    eprintln!("                         Report every error in the program without running it,");
+    eprintln!("                         optionally as JSON for editors");
+    eprintln!("    list <program.ram>   Print the assembler listing of the program");
+    eprintln!("    xref <program.ram>   Print the labels with the instructions referring to them");
+}
+
+/// Reads the whole file, printing the error on failure
+fn read_source(path: &str) -> Option<String> {
+    match std::fs::read_to_string(path) {
+        Ok(code) => Some(code),
+        Err(error) => {
+            eprintln!("ERROR: Could not read `{path}`: {error}");
+            None
+        }
+    }
+}
+
+/// Reads the program source and expands its includes, printing the error on failure
+fn read_program(path: &str) -> Option<Preprocessed> {
+    match preprocess_with_locations(Path::new(path)) {
+        Ok(program) => Some(program),
+        Err(error) => {
+            eprintln!("{error}");
+            None
+        }
+    }
+}
 
-    let mut exp = NewParser::default();
-    exp.parse_source(&code);
-    dbg!(exp.tokens);
+/// Prints the parse error. When the program includes other files, the original location of the
+/// line is printed as well, since line numbers refer to the source with includes expanded
+fn report_parse_error(error: &ParseError, program: &Preprocessed) {
+    eprintln!("{error}");
+
+    let location = error.line().and_then(|line| program.location(line));
+    if let Some(location) = location.filter(|_| program.has_includes()) {
+        eprintln!("    in `{}`, line {}", location.file.display(), location.line);
+    }
+}
 
-    let instructions = match parser.parse_source_new(code) {
-        Ok(inst) => inst,
-        Err(message) => {
-            eprintln!("{message}");
-            return;
+/// Imports the program from the JSON format, printing the error on failure
+fn load_json_program(path: &str) -> Option<RAM> {
+    let text = read_source(path)?;
+    let instructions = match from_json(&text) {
+        Ok(instructions) => instructions,
+        Err(error) => {
+            eprintln!("{error}");
+            return None;
         }
     };
 
     let mut ram = RAM::new();
-    ram.load_instructions(instructions);
+    if let Err(error) = ram.load_instructions(instructions) {
+        eprintln!("{error}");
+        return None;
+    }
+    Some(ram)
+}
+
+/// Loads, validates and parses the program, printing the errors on failure and the warnings
+/// otherwise. Files with the `.json` extension are imported from the JSON format
+fn load_program(path: &str) -> Option<RAM> {
+    if path.ends_with(".json") {
+        return load_json_program(path);
+    }
+
+    let program = read_program(path)?;
+
+    // Every error is reported before giving up, not only the first one
+    if let Err(errors) = parser::validate(&program.source) {
+        for error in errors {
+            report_parse_error(&error, &program);
+        }
+        return None;
+    }
+
+    let mut parser = Parser::default();
+    let output = match parser.parse(&program.source) {
+        Ok(output) => output,
+        Err(error) => {
+            report_parse_error(&error, &program);
+            return None;
+        }
+    };
+
+    for warning in &output.warnings {
+        eprintln!("{warning}");
+    }
+
+    let mut ram = RAM::new();
+    if let Err(error) = ram.load_instructions(output.instructions) {
+        eprintln!("{error}");
+        return None;
+    }
+    ram.set_entry_point(output.entry_point);
+    ram.set_labels(output.label_map);
+    Some(ram)
+}
+
+/// Runs the program without the graphical interface until it halts. When `input_path` is given,
+/// the whole input is read from that file up front instead of the standard input. With `json`
+/// set, the written values are collected and the final state is printed as JSON instead
+fn run_headless(path: &str, input_path: Option<&str>, json: bool) -> ExitCode {
+    let Some(mut ram) = load_program(path) else {
+        return ExitCode::from(EXIT_USAGE_ERROR);
+    };
+
+    if let Some(input_path) = input_path {
+        let Some(text) = read_source(input_path) else {
+            return ExitCode::from(EXIT_USAGE_ERROR);
+        };
+
+        let input = VecInput::from_text(&text);
+        if let Some(value) = input.first_invalid() {
+            eprintln!("ERROR: Incorrect input data in `{input_path}`: `{value}` is not a 32 bit integer");
+            return ExitCode::from(EXIT_USAGE_ERROR);
+        }
+        ram.set_input(Box::new(input));
+    }
+
+    if json {
+        ram.set_output(Box::new(VecOutput::default()));
+    }
 
-    ram.print_instruction_stack();
+    ram.set_overflow_tracking(true);
+    let result = ram.run().map_err(RamError::from);
+    if json {
+        println!("{}", ram.state_json());
+    }
+    if ram.overflow_occurred() {
+        eprintln!("WARNING: An arithmetic instruction overflowed 32 bits, the results may be incorrect.");
+    }
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{error}");
+            exit_code(&error)
+        }
+    }
+}
+
+fn run_debug(path: &str) -> ExitCode {
+    let Some(ram) = load_program(path) else {
+        return ExitCode::from(EXIT_USAGE_ERROR);
+    };
+
+    repl::run_debugger(ram);
+    ExitCode::SUCCESS
+}
+
+fn run_check(path: &str) -> ExitCode {
+    let Some(program) = read_program(path) else {
+        return ExitCode::from(EXIT_USAGE_ERROR);
+    };
+
+    match parser::validate(&program.source) {
+        Ok(()) => {
+            if let Ok(output) = Parser::default().parse(&program.source) {
+                if !output.metadata.is_empty() {
+                    println!("{}", output.metadata);
+                }
+                for warning in output.warnings {
+                    eprintln!("{warning}");
+                }
+            }
+            ExitCode::SUCCESS
+        }
+        Err(errors) => {
+            for error in errors {
+                report_parse_error(&error, &program);
+            }
+            ExitCode::from(EXIT_USAGE_ERROR)
+        }
+    }
+}
+
+/// Prints errors and warnings of the program as a JSON array, so that editors can show them
+fn run_check_json(path: &str) -> ExitCode {
+    let Some(program) = read_program(path) else {
+        return ExitCode::from(EXIT_USAGE_ERROR);
+    };
+
+    println!("{}", diagnose_json(&program.source));
+    match parser::validate(&program.source) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(_) => ExitCode::from(EXIT_USAGE_ERROR),
+    }
+}
 
-    while let Some(inst) =  ram.execute_next_instruction() {
-        println!("Executed: {inst}");
+fn run_list(path: &str) -> ExitCode {
+    let Some(program) = read_program(path) else {
+        return ExitCode::from(EXIT_USAGE_ERROR);
+    };
+
+    match assembler::assemble(&program.source) {
+        Ok(listing) => {
+            print!("{listing}");
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            report_parse_error(&error, &program);
+            exit_code(&error.into())
+        }
+    }
+}
+
+fn run_xref(path: &str) -> ExitCode {
+    let Some(program) = read_program(path) else {
+        return ExitCode::from(EXIT_USAGE_ERROR);
+    };
+
+    match assembler::cross_reference(&program.source) {
+        Ok(report) => {
+            print!("{report}");
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            report_parse_error(&error, &program);
+            exit_code(&error.into())
+        }
+    }
+}
+
+fn run_gui() -> ExitCode {
+    match run_app() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("ERROR: Could not start the graphical interface: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    let program = args.first().map(String::as_str).unwrap_or("ramulator");
+
+    match args.get(1..).unwrap_or_default() {
+        [] => run_gui(),
+        [command] if command == "gui" => run_gui(),
+        [command, flag, path] if command == "run" && flag == "--json" => run_headless(path, None, true),
+        [command, flag, path, input] if command == "run" && flag == "--json" => {
+            run_headless(path, Some(input), true)
+        }
+        [command, path] if command == "run" => run_headless(path, None, false),
+        [command, path, input] if command == "run" => run_headless(path, Some(input), false),
+        [command, path] if command == "debug" => run_debug(path),
+        [command, path] if command == "check" => run_check(path),
+        [command, path, flag] if command == "check" && flag == "--json" => run_check_json(path),
+        [command, path] if command == "list" => run_list(path),
+        [command, path] if command == "xref" => run_xref(path),
+        _ => {
+            print_usage(program);
+            ExitCode::from(EXIT_USAGE_ERROR)
+        }
     }
 }
@@ -1,3 +1,7 @@
+use alloc::{string::String, vec::Vec};
+
+use crate::{display_column, source_lines, COMMENT_CHARS, DEFAULT_TAB_WIDTH};
+
 // Line is expected to be:
 // (multiple labels) (instruction) (comment)
 //  ^ single string   ^ string list   ^ single string
@@ -17,56 +21,96 @@ pub enum Token {
     NewLine
 }
 
-#[derive(Default)]
 pub struct NewParser {
     pub tokens: Vec<Token>,
+    /// Columns (starting from 1) the tokens begin at, `columns[i]` belongs to `tokens[i]`
+    pub columns: Vec<usize>,
+    /// Number of columns a tab advances to
+    pub tab_width: usize,
+}
+
+impl Default for NewParser {
+    fn default() -> Self {
+        Self { tokens: Vec::new(), columns: Vec::new(), tab_width: DEFAULT_TAB_WIDTH }
+    }
 }
 
 impl NewParser {
-    fn tokenize_word(&mut self, mut word: String, found_opcode: &mut bool) {
+    fn push_token(&mut self, token: Token, column: usize) {
+        self.tokens.push(token);
+        self.columns.push(column);
+    }
+
+    fn tokenize_word(&mut self, mut word: String, column: usize, found_opcode: &mut bool) {
         if word.is_empty() {
             return;
         }
 
+        // `loop :` declares a label the same way as `loop:`
+        if word == ":" && matches!(self.tokens.last(), Some(Token::InstrName(_))) {
+            if let Some(Token::InstrName(name)) = self.tokens.pop() {
+                *found_opcode = false;
+                self.tokens.push(Token::Label(name));
+                return;
+            }
+        }
+
         if word.ends_with(':') {
             word.pop();
-            self.tokens.push(Token::Label(word))
+            self.push_token(Token::Label(word), column)
         } else if !*found_opcode {
             *found_opcode = true;
-            self.tokens.push(Token::InstrName(word))
+            self.push_token(Token::InstrName(word), column)
         } else {
-            self.tokens.push(Token::InstrValue(word))
+            self.push_token(Token::InstrValue(word), column)
         }
     }
 
     pub fn parse_line(&mut self, line: &str) {
+        // Windows line ending would otherwise end up in the trailing comment
+        let line = line.strip_suffix('\r').unwrap_or(line);
         let mut found_opcode = false;
+        let tab_width = self.tab_width.max(1);
 
         let mut word = String::new();
+        // Columns are counted from 0 here and reported starting from 1
+        let mut column = 0;
+        let mut word_column = 0;
         let mut chars = line.chars();
         while let Some(c) = chars.next() {
             match c {
-                ';' => {
+                _ if COMMENT_CHARS.contains(&c) => {
                     let comment = String::from(chars.as_str());
-                    self.tokens.push(Token::Comment(comment));
+                    self.push_token(Token::Comment(comment), column + 1);
                     break;
                 }
-                '=' | '*' if word.is_empty() => self.tokens.push(Token::InstrType(c)),
+                '=' | '*' | '@' | '~' if word.is_empty() => self.push_token(Token::InstrType(c), column + 1),
                 _ if c.is_whitespace() => {
-                    self.tokenize_word(word, &mut found_opcode);
+                    self.tokenize_word(word, word_column + 1, &mut found_opcode);
                     word = String::new();
                 }
-                _ => word.push(c),
+                _ => {
+                    if word.is_empty() {
+                        word_column = column;
+                    }
+                    word.push(c);
+                }
             }
+
+            column = match c {
+                '\t' => (column / tab_width + 1) * tab_width,
+                _ => column + 1,
+            };
         }
 
-        self.tokenize_word(word, &mut found_opcode);
+        self.tokenize_word(word, word_column + 1, &mut found_opcode);
     }
 
     pub fn parse_source(&mut self, source: &str) {
-        for line in source.lines() {
+        for line in source_lines(source) {
             self.parse_line(line);
-            self.tokens.push(Token::NewLine);
+            let end = display_column(line, line.len(), self.tab_width);
+            self.push_token(Token::NewLine, end);
         }
     }
 }
@@ -1,11 +1,14 @@
-use std::collections::HashMap;
+use alloc::{collections::{BTreeMap, BTreeSet}, format, string::{String, ToString}, vec, vec::Vec};
 
-use crate::{Instruction, OpCode, OpType};
+use crate::analysis::{find_division_by_zero, find_jump_targets, find_trivial_infinite_loops, find_unreachable, Warning};
+use crate::{
+    display_column, parse_integer, parse_wide_integer, source_lines, Instruction, OpCode, OpType, SourceLine,
+    COMMENT_CHARS, DEFAULT_TAB_WIDTH,
+};
 
 /// Responsible for parsing RAM source into instructions.
 ///
 /// Stores required information to create *correct* RAM instruction code
-#[derive(Default)]
 pub struct Parser {
     /// Points at the current instruction 
     cursor: usize,
@@ -13,51 +16,411 @@ pub struct Parser {
     missing_labels: Vec::<(String, usize)>,
     // (TODO: improve this description)
     /// Stores jump instruction positions that are missing the jump index (??????)
-    label_map: HashMap::<String, usize>,
+    label_map: BTreeMap::<String, usize>,
+    /// Stores constants declared with the `CONST` or `EQU` directives and their values
+    constants: BTreeMap::<String, i32>,
+    /// Source line numbers (starting from 1) of the parsed instructions
+    instruction_lines: Vec<usize>,
+    /// Label declared with the `START` directive, execution of the program begins at it
+    start_label: Option<String>,
+    /// Line of the `START` directive
+    start_line: usize,
+    /// Number of the currently parsed line, starting from 1
+    line: usize,
+    /// Warnings found while parsing the lines, followed by the ones found in the whole program
+    warnings: Vec<Warning>,
+    /// Allows labels only as arguments of jump instructions, see [`Parser::strict`]
+    strict: bool,
+    /// Reports literals that do not fit in a register as errors, see
+    /// [`Parser::set_reject_overflow`]
+    reject_overflow: bool,
+    /// Number of columns a tab advances to, used for the reported columns
+    tab_width: usize,
+    /// Column (starting from 1) of the currently parsed token
+    column: usize,
+    /// Columns of the parsed lines, by the line number. It is the column of the instruction or of
+    /// the token that caused an error in that line
+    columns: BTreeMap<usize, usize>,
+    /// Inverse of `label_map`, labels pointing to each instruction index sorted by name. Built
+    /// once the whole source is parsed
+    index_labels: BTreeMap<usize, Vec<String>>,
+    /// Mnemonics of the extension instructions accepted next to the built-in ones, see
+    /// [`Parser::allow_extension`]
+    extensions: BTreeSet<String>,
+    /// Labels declared in the currently parsed statement
+    statement_labels: Vec<String>,
+    /// Statements of the parsed source, see [`Parser::parse_document`]
+    document: Vec<DocumentItem>,
 }
 
-enum ParsingError {
-    InvalidInstructionError(String),
-    ReapeatingLabelError(String),
-    EmptyLabelError,
+impl Default for Parser {
+    fn default() -> Self {
+        Self {
+            cursor: 0,
+            missing_labels: Vec::new(),
+            label_map: BTreeMap::new(),
+            constants: BTreeMap::new(),
+            instruction_lines: Vec::new(),
+            start_label: None,
+            start_line: 0,
+            line: 0,
+            warnings: Vec::new(),
+            strict: false,
+            reject_overflow: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            column: 1,
+            columns: BTreeMap::new(),
+            index_labels: BTreeMap::new(),
+            extensions: BTreeSet::new(),
+            statement_labels: Vec::new(),
+            document: Vec::new(),
+        }
+    }
 }
 
-enum ParsingSuccess {
-    Instruction(Instruction),
-    EmptyLine,
-    JumpLabel,
-    Comment,
+/// Everything the parser knows about the parsed source
+#[derive(Debug, Clone, Default)]
+pub struct ParseOutput {
+    /// Parsed instructions with resolved labels
+    pub instructions: Vec<Instruction>,
+    /// Labels and indices of the instructions they point to
+    pub label_map: BTreeMap<String, usize>,
+    /// Source line numbers (starting from 1) of the instructions
+    pub source_lines: Vec<usize>,
+    /// Non-fatal problems found in the source
+    pub warnings: Vec<Warning>,
+    /// Index of the instruction the execution begins at
+    pub entry_point: usize,
+    /// Information about the program from the comments at the top of the source
+    pub metadata: ProgramMetadata,
+}
+
+/// Information about the program written in the comments at the top of the source as
+/// `; @key: value`, for example `;@name: add_numbers`. Unknown keys are ignored
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgramMetadata {
+    /// Name of the program, from `@name`
+    pub name: Option<String>,
+    /// Author of the program, from `@author`
+    pub author: Option<String>,
+    /// Short description of what the program does, from `@description`
+    pub description: Option<String>,
+}
+
+impl ProgramMetadata {
+    /// Returns `true` when none of the keys were given
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.author.is_none() && self.description.is_none()
+    }
+}
+
+impl core::fmt::Display for ProgramMetadata {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.name.as_deref().unwrap_or("Unnamed program"))?;
+        if let Some(author) = &self.author {
+            write!(f, " by {author}")?;
+        }
+        if let Some(description) = &self.description {
+            write!(f, "\n{description}")?;
+        }
+        Ok(())
+    }
 }
 
+/// Reads the `@key: value` pairs from the comment lines at the top of the source. Scanning stops
+/// at the first line with code, comments that are not pairs are skipped
+pub fn parse_metadata(source: &str) -> ProgramMetadata {
+    let mut metadata = ProgramMetadata::default();
+    for line in source_lines(source) {
+        let (code, comment) = split_comment(line);
+        if !code.trim().is_empty() {
+            break;
+        }
+
+        let Some((key, value)) = comment.and_then(|text| text.trim().strip_prefix('@')?.split_once(':')) else {
+            continue;
+        };
+
+        let value = Some(value.trim().to_string()).filter(|value| !value.is_empty());
+        match key.trim() {
+            "name" => metadata.name = value,
+            "author" => metadata.author = value,
+            "description" => metadata.description = value,
+            _ => {}
+        }
+    }
+    metadata
+}
+
+/// Part of the source kept by [`Parser::parse_document`], in the order of appearance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DocumentItem {
+    /// Instruction with the given index in [`Document::instructions`]
+    Instruction { line: usize, index: usize },
+    /// Label declaration, either alone or in front of an instruction in the same line
+    Label { line: usize, name: String },
+    /// Comment that is the only thing in the statement, the text includes the comment character
+    Comment { line: usize, text: String },
+    /// Line without any code or comment
+    Blank { line: usize },
+    /// `CONST`, `EQU`, `START` or `END` directive, with its whole text
+    Directive { line: usize, text: String },
+}
+
+/// Source parsed with [`Parser::parse_document`], every statement is kept, not only instructions
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Document {
+    /// Statements of the source in the order they appear in
+    pub items: Vec<DocumentItem>,
+    /// Parsed instructions with resolved labels
+    pub instructions: Vec<Instruction>,
+    /// Errors found in the source, statements with errors have no items
+    pub errors: Vec<ParseError>,
+}
+
+/// Errors that can occur while parsing RAM source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// Instruction with the given name does not exist
+    InvalidInstruction { line: usize, name: String },
+    /// Label is declared more than once
+    RepeatingLabel { line: usize, label: String },
+    /// Label declaration has no name
+    EmptyLabel { line: usize },
+    /// Constant is declared more than once
+    RepeatingConstant { line: usize, name: String },
+    /// Constant declaration is missing its name or has an incorrect value
+    InvalidConstant { line: usize, name: String },
+    /// Constant and label are declared with the same name
+    NameCollision { line: usize, name: String },
+    /// Constant is named after an instruction or a directive, for example `ADD EQU 5`
+    ReservedName { line: usize, name: String },
+    /// Label used as an argument (or by the `START` directive) in the given line was never
+    /// declared
+    LabelNotFound { line: usize, label: String },
+    /// Label is used by the instruction at `index`, which was not parsed
+    DanglingLabelUse { label: String, index: usize },
+    /// Jump instruction at `index` points past the end of the instruction stack
+    JumpOutOfRange { index: usize, target: i32 },
+    /// Instruction cannot be used with the type of the given argument, for example `STORE =1`
+    InvalidOperand { line: usize, instruction: String },
+    /// Integer literal used as an argument does not fit in a register, reported only when
+    /// [`Parser::set_reject_overflow`] is enabled
+    ImmediateOutOfRange { line: usize, literal: String },
+    /// `START` directive is used more than once or without a label
+    InvalidStart { line: usize },
+}
+
+impl core::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseError::InvalidInstruction { line, name } => {
+                write!(f, "ERROR: Exception in line {line}. Instruction `{name}` does not exist.")
+            }
+            ParseError::RepeatingLabel { line, label } => {
+                write!(f, "ERROR: Exception in line {line}. Label {label} is declared in multiple places. You cannot have more than one label with the same name")
+            }
+            ParseError::EmptyLabel { line } => {
+                write!(f, "ERROR: Exception in line {line}. Label cannot be an empty string.")
+            }
+            ParseError::RepeatingConstant { line, name } => {
+                write!(f, "ERROR: Exception in line {line}. Constant {name} is declared in multiple places. You cannot have more than one constant with the same name")
+            }
+            ParseError::InvalidConstant { line, name } => {
+                write!(f, "ERROR: Exception in line {line}. Constant `{name}` must be declared with an integer value or a previously declared constant.")
+            }
+            ParseError::NameCollision { line, name } => {
+                write!(f, "ERROR: Exception in line {line}. Name `{name}` is declared both as a label and as a constant.")
+            }
+            ParseError::ReservedName { line, name } => {
+                write!(f, "ERROR: Exception in line {line}. Constant cannot be named `{name}`, the name belongs to an instruction or a directive.")
+            }
+            ParseError::LabelNotFound { line, label } => {
+                write!(f, "ERROR: Exception in line {line}. Label named `{label}` not found.")
+            }
+            ParseError::DanglingLabelUse { label, index } => {
+                write!(f, "ERROR: Exception thrown. Label named `{label}` is used by instruction number {index}, which does not exist.")
+            }
+            ParseError::JumpOutOfRange { index, target } => {
+                write!(f, "ERROR: Exception thrown. Jump instruction number {index} points to instruction {target}, which does not exist.")
+            }
+            ParseError::InvalidOperand { line, instruction } => {
+                write!(f, "ERROR: Exception in line {line}. Instruction `{instruction}` has an argument of incorrect type.")
+            }
+            ParseError::InvalidStart { line } => {
+                write!(f, "ERROR: Exception in line {line}. START directive requires a label and can be used only once.")
+            }
+            ParseError::ImmediateOutOfRange { line, literal } => {
+                write!(f, "ERROR: Exception in line {line}. Value `{literal}` does not fit in a 32 bit register.")
+            }
+        }
+    }
+}
+
+impl ParseError {
+    /// Line of the source the error was found in, if the error is tied to a single line
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            ParseError::InvalidInstruction { line, .. }
+            | ParseError::RepeatingLabel { line, .. }
+            | ParseError::EmptyLabel { line }
+            | ParseError::RepeatingConstant { line, .. }
+            | ParseError::InvalidConstant { line, .. }
+            | ParseError::NameCollision { line, .. }
+            | ParseError::ReservedName { line, .. }
+            | ParseError::InvalidOperand { line, .. }
+            | ParseError::InvalidStart { line }
+            | ParseError::ImmediateOutOfRange { line, .. }
+            | ParseError::LabelNotFound { line, .. } => Some(*line),
+            ParseError::DanglingLabelUse { .. }
+            | ParseError::JumpOutOfRange { .. } => None,
+        }
+    }
+}
+
+impl core::error::Error for ParseError {}
+
 enum ParsingResult {
     Instruction(Instruction),
     EmptyLine,
     JumpLabel,
     Comment,
+    Constant,
+    StartDirective,
+    EndDirective,
 
     InvalidInstructionError(String),
     ReapeatingLabelError(String),
     EmptyLabelError,
+    RepeatingConstantError(String),
+    InvalidConstantError(String),
+    NameCollisionError(String),
+    ReservedNameError(String),
+    InvalidStartError,
+    InvalidOperandError(String),
+    ImmediateOutOfRangeError(String),
 }
 
 // TODO: More verbose error on parsing, and don't use the crappy panic
 // TODO: Add a way to verify whether an instruction is correct or not. 
 impl Parser {
-    fn parse_instruction_new(&mut self, line: &str) -> ParsingResult {
-        let mut data = line.split_whitespace();
+    /// Creates a parser that accepts labels only as arguments of jump instructions. A typo such as
+    /// `ADD fifteen` is then reported as an invalid operand in its own line, instead of a missing
+    /// label
+    pub fn strict() -> Self {
+        Self { strict: true, ..Self::default() }
+    }
 
-        let mut opcode_string = if let Some(opcode_str) = data.next() {
-            opcode_str.to_string()
-        } else {
-            return ParsingResult::EmptyLine;
+    /// Reports integer literals that do not fit in a 32 bit register, such as `LOAD =10000000000`,
+    /// as [`ParseError::ImmediateOutOfRange`]. By default they wrap around and only
+    /// [`Warning::ConstantOverflow`] is reported
+    pub fn set_reject_overflow(&mut self, reject: bool) {
+        self.reject_overflow = reject;
+    }
+
+    /// Sets the number of columns a tab advances to, so that the reported columns match the ones
+    /// shown by the editor. Defaults to [`DEFAULT_TAB_WIDTH`]
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = tab_width;
+    }
+
+    /// Accepts the uppercase mnemonic `name` as an extension instruction, parsed into
+    /// [`OpCode::EXT`]. Names of the built-in instructions cannot be overridden. The machine
+    /// running the program needs a handler registered with
+    /// [`RAM::register_extension`](crate::ram::RAM::register_extension)
+    pub fn allow_extension(&mut self, name: &str) {
+        self.extensions.insert(name.to_string());
+    }
+
+    /// Remembers `token`, which has to be a part of `line`, as the currently parsed token
+    fn set_column(&mut self, line: &str, token: &str) {
+        let index = token.as_ptr() as usize - line.as_ptr() as usize;
+        self.column = display_column(line, index, self.tab_width);
+    }
+
+    /// Declares a constant from the `CONST name value` or `name EQU value` directive
+    fn parse_constant(&mut self, name: Option<&str>, value: Option<&str>) -> ParsingResult {
+        let Some(name) = name else {
+            return ParsingResult::InvalidConstantError(String::new());
         };
 
-        // The ; sign at the start of the string is considered to be a comment in my
-        // implementation
-        if opcode_string.starts_with(';') {
-            return ParsingResult::Comment;
+        let Some(value) = value else {
+            return ParsingResult::InvalidConstantError(name.to_string());
+        };
+
+        // Constants can be defined with the use of previously declared constants
+        let value = match parse_integer(value) {
+            Some(value) => value,
+            None => match self.constants.get(value) {
+                Some(value) => *value,
+                None => return ParsingResult::InvalidConstantError(name.to_string()),
+            },
+        };
+
+        if self.constants.contains_key(name) {
+            return ParsingResult::RepeatingConstantError(name.to_string());
+        }
+        if self.is_reserved_name(name) {
+            return ParsingResult::ReservedNameError(name.to_string());
+        }
+        // A constant would silently take the place of the label used as an argument
+        if self.label_map.contains_key(name) {
+            return ParsingResult::NameCollisionError(name.to_string());
+        }
+        self.constants.insert(name.to_string(), value);
+
+        ParsingResult::Constant
+    }
+
+    /// Returns `true` if the name belongs to an instruction (including the allowed extensions) or
+    /// to a directive, so it cannot be used as a name of a constant
+    fn is_reserved_name(&self, name: &str) -> bool {
+        OpCode::from_name(name).is_some()
+            || self.extensions.contains(name)
+            || matches!(name, "CONST" | "EQU" | "START" | "END" | "EOF")
+    }
+
+    /// Value of the argument without its type sign, an integer literal or a constant. Returns
+    /// `Ok(None)` when it is neither, so it may be a label
+    fn literal_value(&mut self, line: &str, literal: &str) -> Result<Option<i32>, ParsingResult> {
+        if let Some(value) = parse_integer(literal) {
+            return Ok(Some(value));
+        }
+
+        if let Some(value) = parse_wide_integer(literal) {
+            if self.reject_overflow {
+                self.set_column(line, literal);
+                return Err(ParsingResult::ImmediateOutOfRangeError(literal.to_string()));
+            }
+            self.warnings.push(Warning::ConstantOverflow { line: self.line, literal: literal.to_string() });
+            return Ok(Some(value as i32));
         }
 
+        Ok(self.constants.get(literal).copied())
+    }
+
+    /// Parses a single statement, which is the whole `line` or one of its parts separated with `|`
+    fn parse_instruction_new(&mut self, line: &str, statement: &str) -> ParsingResult {
+        self.set_column(line, statement.trim_start());
+
+        let (code, comment) = split_comment(statement);
+        let comment = comment.map(str::trim);
+        let mut data = code_tokens(code);
+
+        let mut opcode_string = match data.next() {
+            Some(opcode_str) => {
+                self.set_column(line, opcode_str);
+                opcode_str.to_string()
+            }
+            None if comment.is_some() => return ParsingResult::Comment,
+            None => return ParsingResult::EmptyLine,
+        };
+
+        // Trailing comment is kept with the instruction, so that it can be written back out
+        let comment = comment.filter(|text| !text.is_empty()).map(String::from);
+
+        attach_label_colon(&mut opcode_string, &mut data);
+
         // Strings that end with the : are considered to be jump labels
         while opcode_string.ends_with(':') {
             opcode_string.pop();
@@ -68,73 +431,160 @@ impl Parser {
             if self.label_map.contains_key(&opcode_string) {
                 return ParsingResult::ReapeatingLabelError(opcode_string);
             }
+            if self.constants.contains_key(&opcode_string) {
+                return ParsingResult::NameCollisionError(opcode_string);
+            }
+            self.statement_labels.push(opcode_string.clone());
             self.label_map.insert(opcode_string, self.cursor);
 
 
             opcode_string = if let Some(opcode_str) = data.next() {
+                self.set_column(line, opcode_str);
                 opcode_str.to_string()
             } else {
                 return ParsingResult::JumpLabel;
             };
+            attach_label_colon(&mut opcode_string, &mut data);
+        }
+
+        // End of the program, `END` or `EOF`. Everything below it is not parsed, unlike `HALT` it
+        // is not an instruction
+        if opcode_string == "END" || opcode_string == "EOF" {
+            return ParsingResult::EndDirective;
+        }
+
+        // Entry point directive, `START label`
+        if opcode_string == "START" {
+            return match data.next() {
+                Some(label) if self.start_label.is_none() => {
+                    self.start_label = Some(label.to_string());
+                    self.start_line = self.line;
+                    ParsingResult::StartDirective
+                }
+                _ => ParsingResult::InvalidStartError,
+            };
+        }
+
+        // Constant directives, either `CONST name value` or `name EQU value`
+        if opcode_string == "CONST" {
+            return self.parse_constant(data.next(), data.next());
+        }
+
+        if data.clone().next() == Some("EQU") {
+            data.next();
+            return self.parse_constant(Some(&opcode_string), data.next());
         }
 
         // TODO: This could be case insensitive
-        let op_code = match opcode_string.as_str() {
-            "LOAD"  => OpCode::LOAD,
-            "STORE" => OpCode::STORE,
-            "ADD"   => OpCode::ADD,
-            "SUB"   => OpCode::SUB,
-            "MULT"  => OpCode::MULT,
-            "DIV"   => OpCode::DIV,
-            "READ"  => OpCode::READ,
-            "WRITE" => OpCode::WRITE,
-            "JUMP"  => OpCode::JUMP,
-            "JGTZ"  => OpCode::JGTZ,
-            "JZERO" => OpCode::JZERO,
-            "HALT"  => OpCode::HALT,
-            _       => return ParsingResult::InvalidInstructionError(opcode_string),
+        let (op_code, extension) = match OpCode::from_name(&opcode_string) {
+            Some(op_code) => (op_code, None),
+            None if is_extension_name(&opcode_string) && self.extensions.contains(&opcode_string) => {
+                (OpCode::EXT, Some(opcode_string.clone()))
+            }
+            None => return ParsingResult::InvalidInstructionError(opcode_string),
+        };
+        let value = match data.next() {
+            Some(string) => string,
+            None if !op_code.accepts_operand(&OpType::NoValue) => {
+                return ParsingResult::InvalidOperandError(opcode_string);
+            }
+            None => {
+                // OpCode has no second argument
+                let inst = Instruction {
+                    op_code,
+                    op_type: OpType::NoValue,
+                    op_value: 0,
+                    comment,
+                    source: Some(SourceLine { line: self.line, text: statement.trim().to_string() }),
+                    extension,
+                    op2_type: None,
+                    op2_value: None,
+                };
 
+                self.cursor += 1;
+                return ParsingResult::Instruction(inst);
+            }
         };
-        let string = data.next();
-        let value = if string.is_some() && !string.unwrap().starts_with(';') {
-            string.unwrap()
-        } else {
-            // OpCode has no second argument or the argument is a comment
-            let inst = Instruction {
-                op_code,
-                op_type: OpType::NoValue,
-                op_value: 0,
-            };
 
-            self.cursor += 1;
-            return ParsingResult::Instruction(inst);
+        // Legal argument forms:
+        //   =5, =-5, =+5  - immediate value (Value), the sign is optional
+        //   5, +5         - register index (Register)
+        //   *5, *+5       - register index read from the register (ReadReg)
+        //   @5, @-5       - register index relative to the value of the adder (Indexed), the
+        //                   offset can be negative as long as the resulting index is not
+        //   ~3, ~-3       - offset from the jump instruction to its target (Relative), only
+        //                   accepted by jumps
+        //   -5, *-5       - parsed as a register index, but rejected by `validate` since registers
+        //                   cannot have negative indices
+        //   =label, label - index of the instruction the label points to (Value), labels cannot
+        //                   be used with the other signs
+        //
+        // Instructions with a second argument (`SET 5, =42`, `SET 5, 3`) take one of the forms
+        // above twice, the comma between them is optional. The second one cannot be a label
+        let (mut op_type, literal) = split_operand_type(value);
+
+        // Try to parse the value of the argument. If it is not a number, it may be a constant
+        // name. In case of failure, value string is considered to be a label.
+        let op_value = match self.literal_value(line, literal) {
+            Ok(Some(value)) => value,
+            Err(error) => return error,
+            Ok(None) if self.strict && !op_code.is_jump() => {
+                self.set_column(line, value);
+                return ParsingResult::InvalidOperandError(format!("{opcode_string} {value}"));
+            }
+            // Only `label` and `=label` stand for the index of the instruction, the other signs
+            // would be silently dropped
+            Ok(None) if matches!(op_type, OpType::ReadReg | OpType::Indexed | OpType::Relative) => {
+                self.set_column(line, value);
+                return ParsingResult::InvalidOperandError(format!("{opcode_string} {value}"));
+            }
+            Ok(None) => {
+                // The label is used as an index of the instruction, so `=label` loads the index
+                op_type = OpType::Value;
+                self.missing_labels.push((literal.to_string(), self.cursor));
+                // Temporally setting the value to -1, Labels get filled up after the parsing.
+                -1
+            }
         };
 
-        let mut op_type;
-        let mut value_chars = value.chars();
-        if value.starts_with('*') {
-            value_chars.next();
-            op_type = OpType::ReadReg;
-        } else if value.starts_with('=') {
-            value_chars.next();
-            op_type = OpType::Value;
-        } else {
-            op_type = OpType::Register;
+        // Instructions are checked to accept the type of the argument, so `SWAP =1` or `HALT 1`
+        // are rejected here and not when they are executed
+        if !op_code.accepts_operand(&op_type) {
+            self.set_column(line, value);
+            return ParsingResult::InvalidOperandError(format!("{opcode_string} {value}"));
         }
 
-        // Try to parse the value of the second argument. 
-        // In case of failure, value string is considered to be a label.
-        let op_value = if let Ok(value) = value_chars.as_str().parse::<i32>() {
-            value
+        // Commas are skipped with the whitespace, so the second argument is simply the next
+        // token. It cannot be a label
+        let (op2_type, op2_value) = if op_code.has_second_operand() {
+            let Some(second) = data.next() else {
+                self.set_column(line, value);
+                return ParsingResult::InvalidOperandError(format!("{opcode_string} {value}"));
+            };
+
+            let (op2_type, literal) = split_operand_type(second);
+            if !op_code.accepts_second_operand(&op2_type) {
+                self.set_column(line, second);
+                return ParsingResult::InvalidOperandError(format!("{opcode_string} {value} {second}"));
+            }
+            match self.literal_value(line, literal) {
+                Ok(Some(op2_value)) => (Some(op2_type), Some(op2_value)),
+                Err(error) => return error,
+                Ok(None) => {
+                    self.set_column(line, second);
+                    return ParsingResult::InvalidOperandError(format!("{opcode_string} {value} {second}"));
+                }
+            }
         } else {
-            op_type = OpType::Value;
-            self.missing_labels.push((value.to_string(), self.cursor));
-            // Temporally setting the value to -1, Labels get filled up after the parsing.
-            -1
+            (None, None)
         };
 
         let inst = Instruction {
-            op_code, op_type, op_value,
+            op_code, op_type, op_value, comment,
+            source: Some(SourceLine { line: self.line, text: statement.trim().to_string() }),
+            extension,
+            op2_type,
+            op2_value,
         };
 
         self.cursor += 1;
@@ -142,32 +592,32 @@ impl Parser {
     }
 
 
-    /// Parses an instruction and returns it if the parsing succeeded. On failure to function 
-    /// returns None.
-    fn parse_instruction(&mut self, line: &str) -> Option<Instruction> {
+    /// Parses an instruction and returns it if the parsing succeeded. Returns `Ok(None)` for lines
+    /// without an instruction.
+    fn parse_instruction(&mut self, line: &str) -> Result<Option<Instruction>, ParseError> {
         let mut data = line.split_whitespace();
 
         let mut opcode_string = if let Some(opcode_str) = data.next() {
             opcode_str.to_string()
         } else {
-            return None;
+            return Ok(None);
         };
 
-        // The ; sign at the start of the string is considered to be a comment in my
+        // The ; (or #) sign at the start of the string is considered to be a comment in my
         // implementation
-        if opcode_string.starts_with(';') {
-            return None;
+        if opcode_string.starts_with(COMMENT_CHARS) {
+            return Ok(None);
         }
 
         // Strings that end with the : are considered to be jump labels
         while opcode_string.ends_with(':') {
             opcode_string.pop();
             if opcode_string.is_empty() {
-                panic!("Lable cannot be an empty string");
+                return Err(ParseError::EmptyLabel { line: self.line });
             }
 
             if self.label_map.contains_key(&opcode_string) {
-                panic!("Having two labels with the same name is not allowed");
+                return Err(ParseError::RepeatingLabel { line: self.line, label: opcode_string });
             }
             self.label_map.insert(opcode_string, self.cursor);
 
@@ -175,40 +625,33 @@ impl Parser {
             opcode_string = if let Some(opcode_str) = data.next() {
                 opcode_str.to_string()
             } else {
-                return None;
+                return Ok(None);
             };
         }
 
         // TODO: This could be case insensitive
-        let op_code = match opcode_string.as_str() {
-            "LOAD"  => OpCode::LOAD,
-            "STORE" => OpCode::STORE,
-            "ADD"   => OpCode::ADD,
-            "SUB"   => OpCode::SUB,
-            "MULT"  => OpCode::MULT,
-            "DIV"   => OpCode::DIV,
-            "READ"  => OpCode::READ,
-            "WRITE" => OpCode::WRITE,
-            "JUMP"  => OpCode::JUMP,
-            "JGTZ"  => OpCode::JGTZ,
-            "JZERO" => OpCode::JZERO,
-            "HALT"  => OpCode::HALT,
-            _       => panic!("Given instruction does not exist.")
+        let Some(op_code) = OpCode::from_name(&opcode_string) else {
+            return Err(ParseError::InvalidInstruction { line: self.line, name: opcode_string });
         };
 
-        let string = data.next();
-        let value = if string.is_some() && !string.unwrap().starts_with(';') {
-            string.unwrap()
-        } else {
-            // OpCode has no second argument or the argument is a comment
-            let inst = Instruction {
-                op_code,
-                op_type: OpType::NoValue,
-                op_value: 0,
-            };
+        let value = match data.next() {
+            Some(string) if !string.starts_with(COMMENT_CHARS) => string,
+            _ => {
+                // OpCode has no second argument or the argument is a comment
+                let inst = Instruction {
+                    op_code,
+                    op_type: OpType::NoValue,
+                    op_value: 0,
+                    comment: None,
+                    source: None,
+                    extension: None,
+                    op2_type: None,
+                    op2_value: None,
+                };
 
-            self.cursor += 1;
-            return Some(inst);
+                self.cursor += 1;
+                return Ok(Some(inst));
+            }
         };
 
         let mut op_type;
@@ -233,65 +676,651 @@ impl Parser {
         };
 
         let inst = Instruction {
-            op_code, op_type, op_value,
+            op_code, op_type, op_value, comment: None, source: None, extension: None, op2_type: None,
+            op2_value: None,
         };
 
         self.cursor += 1;
-        Some(inst)
+        Ok(Some(inst))
     }
 
-    // TODO: This should also return result at some point
-    pub fn parse_source_new(&mut self, source: String) -> Result<Vec<Instruction>, String> {
+    /// Parses the source without stopping on the first error, so that all of them can be reported
+    fn parse_collecting(&mut self, source: &str) -> (Vec<Instruction>, Vec<ParseError>) {
         let mut instruction_stack = Vec::new();
+        let mut errors = Vec::new();
 
-        let mut temp = 1;
-        for line in source.lines() {
-            match self.parse_instruction_new(line) {
-                ParsingResult::Instruction(inst) => instruction_stack.push(inst),
-                ParsingResult::ReapeatingLabelError(label_name) => {
-                    return Err(format!("ERROR: Exception in line {temp}. Label {label_name} is declared in multiple places. You cannot have more than one label with the same name"));
-                }
-                ParsingResult::InvalidInstructionError(inst_code) => {
-                    return Err(format!("ERROR: Exception in line {temp}. Instruction `{inst_code}` does not exist."));
+        let mut lines = (1..).zip(source_lines(source));
+        'lines: while let Some((line, text)) = lines.next() {
+            self.line = line;
+
+            // Statements of a single line share its number. The column of the line is the column
+            // of its first statement, or of the first error in it
+            let mut statements = split_statements(text).into_iter();
+            let mut first = true;
+            let mut line_has_error = false;
+            while let Some(statement) = statements.next() {
+                self.statement_labels.clear();
+                let result = self.parse_instruction_new(text, statement);
+                if core::mem::take(&mut first) {
+                    self.columns.insert(line, self.column);
                 }
-                ParsingResult::EmptyLabelError => {
-                    return Err(format!("ERROR: Exception in line {temp}. Label cannot be an empty string."));
+                self.document_statement(line, statement, &result, instruction_stack.len());
+
+                let error = match result {
+                    ParsingResult::Instruction(inst) => {
+                        instruction_stack.push(inst);
+                        self.instruction_lines.push(line);
+                        continue;
+                    }
+                    ParsingResult::EndDirective => {
+                        let is_ignored = |text: &str| !text.trim().is_empty();
+                        if statements.any(is_ignored) || lines.any(|(_, text)| is_ignored(text)) {
+                            self.warnings.push(Warning::IgnoredAfterEnd { line });
+                        }
+                        break 'lines;
+                    }
+                    ParsingResult::ReapeatingLabelError(label) => ParseError::RepeatingLabel { line, label },
+                    ParsingResult::InvalidInstructionError(name) => ParseError::InvalidInstruction { line, name },
+                    ParsingResult::EmptyLabelError => ParseError::EmptyLabel { line },
+                    ParsingResult::RepeatingConstantError(name) => ParseError::RepeatingConstant { line, name },
+                    ParsingResult::InvalidConstantError(name) => ParseError::InvalidConstant { line, name },
+                    ParsingResult::NameCollisionError(name) => ParseError::NameCollision { line, name },
+                    ParsingResult::ReservedNameError(name) => ParseError::ReservedName { line, name },
+                    ParsingResult::InvalidStartError => ParseError::InvalidStart { line },
+                    ParsingResult::InvalidOperandError(instruction) => ParseError::InvalidOperand { line, instruction },
+                    ParsingResult::ImmediateOutOfRangeError(literal) => ParseError::ImmediateOutOfRange { line, literal },
+                    _ => continue,
+                };
+
+                if !core::mem::replace(&mut line_has_error, true) {
+                    self.columns.insert(line, self.column);
                 }
-                _ => {},
+                errors.push(error);
             }
-
-            temp += 1;
         }
 
         // Filling the missing jump values
         for label in &self.missing_labels {
+            // Indices come from the cursor, which is not reset when the parser is used again
+            let Some(inst) = instruction_stack.get_mut(label.1) else {
+                errors.push(ParseError::DanglingLabelUse { label: label.0.clone(), index: label.1 });
+                continue;
+            };
+
             let Some(value) = self.label_map.get(&label.0) else {
-                return Err(format!("ERROR: Exception thrown. Label named `{label}` not found.", label = label.0));
+                let line = self.instruction_lines[label.1];
+                errors.push(ParseError::LabelNotFound { line, label: label.0.clone() });
+                continue;
             };
 
-            instruction_stack[label.1].op_value = *value as i32;
+            inst.op_value = *value as i32;
+        }
+
+        self.index_labels.clear();
+        for (label, index) in &self.label_map {
+            self.index_labels.entry(*index).or_default().push(label.clone());
+        }
+        for labels in self.index_labels.values_mut() {
+            labels.sort();
+        }
+
+        if let Some(label) = &self.start_label {
+            if !self.label_map.contains_key(label) {
+                errors.push(ParseError::LabelNotFound { line: self.start_line, label: label.clone() });
+            }
+        }
+
+        let len = instruction_stack.len();
+        for (index, inst) in instruction_stack.iter().enumerate() {
+            if let Some(error) = check_jump_target(index, inst, len) {
+                errors.push(error);
+            }
+        }
+
+        (instruction_stack, errors)
+    }
+
+    /// Brings the parser back to its initial state, so that it can parse another source. Labels,
+    /// constants and the `START` directive of the previous source are forgotten, while the settings
+    /// and allowed extensions are kept
+    pub fn reset(&mut self) {
+        *self = Self {
+            strict: self.strict,
+            reject_overflow: self.reject_overflow,
+            tab_width: self.tab_width,
+            extensions: core::mem::take(&mut self.extensions),
+            ..Self::default()
+        };
+    }
+
+    /// Index the next parsed instruction gets, equal to the number of instructions parsed so far
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Labels and indices of the instructions they point to, filled during parsing
+    pub fn label_map(&self) -> &BTreeMap<String, usize> {
+        &self.label_map
+    }
+
+    /// Uses of labels as instruction arguments, each with the index of the instruction using it, in
+    /// the order of the source
+    pub fn label_uses(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.missing_labels.iter().map(|(label, index)| (label.as_str(), *index))
+    }
+
+    /// Labels pointing to the instruction at `index`, sorted by name. Empty when there are none or
+    /// the source was not parsed yet
+    pub fn labels_at(&self, index: usize) -> Vec<&str> {
+        self.index_labels
+            .get(&index)
+            .map(|labels| labels.iter().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// Source line numbers (starting from 1) of the parsed instructions
+    pub fn instruction_lines(&self) -> &[usize] {
+        &self.instruction_lines
+    }
+
+    /// Column (starting from 1) of the instruction in the line or of the token that caused an error
+    /// in it, taking the tab width into account. Returns `None` if the line was not parsed
+    pub fn column(&self, line: usize) -> Option<usize> {
+        self.columns.get(&line).copied()
+    }
+
+    /// Source line (starting from 1) of the instruction the label points to. Returns `None` if
+    /// the label does not exist or points past the last instruction
+    pub fn label_line(&self, label: &str) -> Option<usize> {
+        let index = *self.label_map.get(label)?;
+        self.instruction_lines.get(index).copied()
+    }
+
+    /// Index of the instruction the execution of the parsed program begins at.
+    ///
+    /// It is the label declared with the `START` directive or the `main` label. When neither is
+    /// declared, the execution begins at the first instruction.
+    pub fn entry_point(&self) -> usize {
+        let label = self.start_label.as_deref().unwrap_or("main");
+        self.label_map.get(label).copied().unwrap_or(0)
+    }
+
+    /// Parses the source and returns the instructions together with the symbol table, the
+    /// instruction to line mapping and found warnings
+    pub fn parse(&mut self, source: &str) -> Result<ParseOutput, ParseError> {
+        let (instructions, errors) = self.parse_collecting(source);
+        if let Some(error) = errors.into_iter().next() {
+            return Err(error);
+        }
+
+        self.add_analysis_warnings(&instructions);
+
+        Ok(ParseOutput {
+            instructions,
+            label_map: self.label_map.clone(),
+            source_lines: self.instruction_lines.clone(),
+            warnings: self.warnings.clone(),
+            entry_point: self.entry_point(),
+            metadata: parse_metadata(source),
+        })
+    }
+
+    /// Warnings found in the parsed source. Warnings about single statements are kept even when
+    /// parsing fails, the ones that need the whole program are added once the source is parsed
+    /// successfully with [`Parser::parse`] or [`Parser::parse_source_new`]
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Adds the warnings that need the whole program: unused labels, unreachable instructions,
+    /// divisions by a constant zero and jumps to themselves
+    fn add_analysis_warnings(&mut self, instructions: &[Instruction]) {
+        let used_labels: BTreeSet<&str> = self.missing_labels
+            .iter()
+            .map(|(label, _)| label.as_str())
+            .chain(self.start_label.as_deref())
+            .collect();
+
+        let mut unused_labels: Vec<&String> = self.label_map
+            .keys()
+            .filter(|label| !used_labels.contains(label.as_str()))
+            .collect();
+        unused_labels.sort();
+
+        let mut warnings: Vec<Warning> = unused_labels
+            .into_iter()
+            .map(|label| Warning::UnusedLabel { label: label.clone() })
+            .collect();
+
+        let jump_targets = self.label_map.values().copied().chain(find_jump_targets(instructions));
+        for index in find_unreachable(instructions, jump_targets) {
+            let line = self.instruction_lines[index];
+            warnings.push(Warning::UnreachableInstruction { index, line });
+        }
+
+        for index in find_division_by_zero(instructions) {
+            let line = self.instruction_lines[index];
+            warnings.push(Warning::DivisionByConstantZero { index, line });
+        }
+
+        for index in find_trivial_infinite_loops(instructions) {
+            let line = self.instruction_lines[index];
+            warnings.push(Warning::TrivialInfiniteLoop { index, line });
         }
 
+        self.warnings.extend(warnings);
+    }
+
+    /// Parses the source keeping every statement, including comments, blank lines and
+    /// directives, so that the whole document can be reconstructed from the result. Parsing does
+    /// not stop on the first error, all of them are returned in [`Document::errors`]
+    pub fn parse_document(&mut self, source: &str) -> Document {
+        let (instructions, errors) = self.parse_collecting(source);
+        Document { items: core::mem::take(&mut self.document), instructions, errors }
+    }
+
+    /// Adds the items of the statement parsed into `result` to the document. `index` is the
+    /// index the statement gets if it is an instruction
+    fn document_statement(&mut self, line: usize, statement: &str, result: &ParsingResult, index: usize) {
+        let text = statement.trim().to_string();
+        let item = match result {
+            ParsingResult::Instruction(_) => Some(DocumentItem::Instruction { line, index }),
+            ParsingResult::Comment => Some(DocumentItem::Comment { line, text }),
+            ParsingResult::EmptyLine => Some(DocumentItem::Blank { line }),
+            // Comment after a label with no instruction, `loop: ; comment`
+            ParsingResult::JumpLabel => statement
+                .find(COMMENT_CHARS)
+                .map(|start| DocumentItem::Comment { line, text: statement[start..].trim_end().to_string() }),
+            ParsingResult::Constant | ParsingResult::StartDirective | ParsingResult::EndDirective => {
+                Some(DocumentItem::Directive { line, text })
+            }
+            // Statements with errors have no items, not even the labels in front of them
+            _ => return,
+        };
+
+        let labels = self.statement_labels.drain(..).map(|name| DocumentItem::Label { line, name });
+        self.document.extend(labels);
+        self.document.extend(item);
+    }
+
+    // TODO: This should also return result at some point
+    pub fn parse_source_new(&mut self, source: String) -> Result<Vec<Instruction>, String> {
+        let (instruction_stack, errors) = self.parse_collecting(&source);
+        if let Some(error) = errors.first() {
+            return Err(error.to_string());
+        }
+
+        self.add_analysis_warnings(&instruction_stack);
         Ok(instruction_stack)
-   }
+    }
 
-    pub fn parse_source(&mut self, source: String) -> Vec<Instruction> {
+    pub fn parse_source(&mut self, source: String) -> Result<Vec<Instruction>, ParseError> {
         let mut instruction_stack = Vec::new();
 
-        for line in source.lines() {
-            let instruction = self.parse_instruction(line);
-            if let Some(inst) = instruction {
+        for (line, text) in (1..).zip(source_lines(&source)) {
+            self.line = line;
+            if let Some(inst) = self.parse_instruction(text)? {
                 instruction_stack.push(inst);
+                self.instruction_lines.push(line);
             }
         }
 
         // Filling the missing jump values
-        for label in &self.missing_labels {
-            let value = self.label_map[&label.0];
-            instruction_stack[label.1].op_value = value as i32;
+        for (label, index) in &self.missing_labels {
+            let Some(inst) = instruction_stack.get_mut(*index) else {
+                return Err(ParseError::DanglingLabelUse { label: label.clone(), index: *index });
+            };
+
+            let Some(value) = self.label_map.get(label) else {
+                let line = self.instruction_lines[*index];
+                return Err(ParseError::LabelNotFound { line, label: label.clone() });
+            };
+
+            inst.op_value = *value as i32;
+        }
+
+        Ok(instruction_stack)
+    }
+}
+
+/// Returns `true` if the name can be used as a mnemonic of an extension instruction, extensions
+/// are written in uppercase like the built-in instructions, for example `SQRT`
+fn is_extension_name(name: &str) -> bool {
+    name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+        && name.starts_with(|c: char| c.is_ascii_uppercase())
+}
+
+/// Splits the type sign from the argument, so that `*5` is an indirect register 5. Arguments
+/// without a sign are register indices
+fn split_operand_type(value: &str) -> (OpType, &str) {
+    let op_type = match value.chars().next() {
+        Some('*') => OpType::ReadReg,
+        Some('=') => OpType::Value,
+        Some('@') => OpType::Indexed,
+        Some('~') => OpType::Relative,
+        _ => return (OpType::Register, value),
+    };
+    (op_type, &value[1..])
+}
+
+/// Splits the line into statements separated with `|`, so that `LOAD =1 | ADD =2 | HALT` is the
+/// same as three lines with one instruction each. The comment stays with the last statement, `|`
+/// inside of it does not separate anything
+pub(crate) fn split_statements(line: &str) -> Vec<&str> {
+    let (code, _) = split_comment(line);
+    match code.rfind('|') {
+        Some(last) => code[..last].split('|').chain(core::iter::once(&line[last + 1..])).collect(),
+        None => vec![line],
+    }
+}
+
+/// Splits the line into the code and the comment after the comment character. The ; (or #) sign
+/// is considered to be a comment in my implementation. Everything after it is dropped, even if it
+/// is not separated with whitespace, so `ADD 1;note` is `ADD 1`
+pub(crate) fn split_comment(line: &str) -> (&str, Option<&str>) {
+    match line.find(COMMENT_CHARS) {
+        Some(comment_start) => (&line[..comment_start], Some(&line[comment_start + 1..])),
+        None => (line, None),
+    }
+}
+
+/// Words of the code part of the line. Commas are purely cosmetic separators, `ADD, 1`, `ADD,1`
+/// and `ADD , 1` are all `ADD 1`
+pub(crate) fn code_tokens(code: &str) -> impl Iterator<Item = &str> + Clone {
+    code.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty())
+}
+
+/// Appends the colon to the word when it is the next token. Whitespace before the colon of a
+/// label is allowed, so `loop :` is the same as `loop:`
+pub(crate) fn attach_label_colon<'a>(word: &mut String, tokens: &mut (impl Iterator<Item = &'a str> + Clone)) {
+    if tokens.clone().next() == Some(":") {
+        tokens.next();
+        word.push(':');
+    }
+}
+
+/// Returns an error if the jump instruction at `index` has a literal argument pointing outside of
+/// the instruction stack of length `len`
+fn check_jump_target(index: usize, inst: &Instruction, len: usize) -> Option<ParseError> {
+    if !inst.op_code.is_jump() {
+        return None;
+    }
+
+    let in_range = match inst.op_type {
+        OpType::Register | OpType::Value => (0..=len as i64).contains(&(inst.op_value as i64)),
+        OpType::Relative => (0..=len as i64).contains(&(index as i64 + inst.op_value as i64)),
+        OpType::ReadReg | OpType::Indexed | OpType::NoValue => true,
+    };
+
+    if in_range {
+        None
+    } else {
+        Some(ParseError::JumpOutOfRange { index, target: inst.op_value })
+    }
+}
+
+/// Checks whether jump instructions with a literal argument point to existing instructions.
+///
+/// Jumping right past the last instruction is allowed, it simply ends the program. Indirect jumps
+/// (`JUMP *1`) are resolved at runtime and cannot be checked here.
+pub fn validate_jump_targets(instructions: &[Instruction]) -> Result<(), ParseError> {
+    for (index, inst) in instructions.iter().enumerate() {
+        if let Some(error) = check_jump_target(index, inst, instructions.len()) {
+            return Err(error);
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs every static check on the source without executing it.
+///
+/// On top of the regular parsing (including label resolution, jump range and argument type checks)
+/// this also verifies that register indices are not negative. All found errors are returned at
+/// once.
+pub fn validate(source: &str) -> Result<(), Vec<ParseError>> {
+    let mut parser = Parser::default();
+    let (instructions, mut errors) = parser.parse_collecting(source);
+
+    for (inst, line) in instructions.iter().zip(&parser.instruction_lines) {
+        if !inst.has_valid_operand() {
+            errors.push(ParseError::InvalidOperand { line: *line, instruction: inst.to_string() });
         }
+    }
 
-        instruction_stack
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Opcodes, argument types and values of the instructions parsed from the source
+    fn parse_operands(source: &str) -> Vec<(OpCode, OpType, i32)> {
+        let parsed = Parser::default().parse(source).unwrap();
+        parsed
+            .instructions
+            .into_iter()
+            .map(|inst| (inst.op_code, inst.op_type, inst.op_value))
+            .collect()
+    }
+
+    #[test]
+    fn comments_with_both_characters() {
+        let cases: &[(&str, &[Option<&str>])] = &[
+            ("; comment\nHALT", &[None]),
+            ("# comment\nHALT", &[None]),
+            ("LOAD =1 ; one\nHALT # halt", &[Some("one"), Some("halt")]),
+            ("LOAD =1 # one\nHALT ; halt", &[Some("one"), Some("halt")]),
+            ("# first\n; second\nADD 1#note\nHALT;", &[Some("note"), None]),
+            ("LOAD =1 # one ; still one\nHALT", &[Some("one ; still one"), None]),
+            ("start: ; label\nLOAD =5 # five\nend: # label\nHALT ; done", &[Some("five"), Some("done")]),
+        ];
+
+        for (source, comments) in cases {
+            let parsed = Parser::default().parse(source).unwrap();
+            let found: Vec<Option<&str>> = parsed.instructions.iter().map(Instruction::comment).collect();
+            assert_eq!(&found, comments, "source: {source:?}");
+        }
+    }
+
+    #[test]
+    fn operand_signs() {
+        let cases = [
+            ("LOAD =5", OpType::Value, 5),
+            ("LOAD =-5", OpType::Value, -5),
+            ("LOAD =+5", OpType::Value, 5),
+            ("LOAD 5", OpType::Register, 5),
+            ("LOAD +5", OpType::Register, 5),
+            ("LOAD -5", OpType::Register, -5),
+            ("LOAD *5", OpType::ReadReg, 5),
+            ("LOAD *+5", OpType::ReadReg, 5),
+        ];
+
+        for (source, op_type, value) in cases {
+            assert_eq!(parse_operands(source), [(OpCode::LOAD, op_type, value)], "source: {source:?}");
+        }
+    }
+
+    #[test]
+    fn negative_register_indices_are_invalid() {
+        for source in ["LOAD =-5", "LOAD =+5", "LOAD 5", "LOAD +5"] {
+            assert_eq!(validate(source), Ok(()), "source: {source:?}");
+        }
+
+        for source in ["LOAD -5", "LOAD *-5", "STORE -1"] {
+            let errors = validate(source).unwrap_err();
+            assert!(matches!(errors[..], [ParseError::InvalidOperand { line: 1, .. }]), "source: {source:?}");
+        }
+    }
+
+    #[test]
+    fn adder_as_register_zero() {
+        let cases = [
+            ("ADD 0", OpCode::ADD),
+            ("SUB 0", OpCode::SUB),
+            ("MULT 0", OpCode::MULT),
+            ("STORE 0", OpCode::STORE),
+            ("SWAP 0", OpCode::SWAP),
+        ];
+
+        for (source, op_code) in cases {
+            assert_eq!(parse_operands(source), [(op_code, OpType::Register, 0)], "source: {source:?}");
+        }
+    }
+
+    #[test]
+    fn label_colon_placement() {
+        let sources = ["loop: HALT", "loop : HALT", "loop:\nHALT", "loop :\nHALT", "\tloop\t:\tHALT", "loop :   HALT ; done"];
+
+        for source in sources {
+            let mut parser = Parser::default();
+            let parsed = parser.parse(source).unwrap();
+            assert_eq!(parsed.label_map.get("loop"), Some(&0), "source: {source:?}");
+            assert_eq!(parse_operands(source), [(OpCode::HALT, OpType::NoValue, 0)], "source: {source:?}");
+        }
+
+        let parsed = Parser::default().parse("first : second: JUMP first").unwrap();
+        assert_eq!(parsed.label_map.get("first"), Some(&0));
+        assert_eq!(parsed.label_map.get("second"), Some(&0));
+    }
+
+    #[test]
+    fn labels_with_operand_signs() {
+        assert_eq!(parse_operands("JUMP foo\nfoo: HALT")[0], (OpCode::JUMP, OpType::Value, 1));
+        assert_eq!(parse_operands("JUMP =foo\nfoo: HALT")[0], (OpCode::JUMP, OpType::Value, 1));
+
+        for source in ["JUMP ~foo\nfoo: HALT", "ADD @foo\nfoo: HALT", "LOAD *foo\nfoo: HALT"] {
+            let error = Parser::default().parse(source).unwrap_err();
+            assert!(matches!(error, ParseError::InvalidOperand { line: 1, .. }), "source: {source:?}");
+        }
+    }
+
+    #[test]
+    fn constant_name_clashes() {
+        let cases = [
+            ("CONST end 0\nJUMP end\nend: HALT", ParseError::NameCollision { line: 3, name: "end".into() }),
+            ("end: HALT\nCONST end 0", ParseError::NameCollision { line: 2, name: "end".into() }),
+            ("end EQU 1\nend: HALT", ParseError::NameCollision { line: 2, name: "end".into() }),
+            ("ADD EQU 5\nHALT", ParseError::ReservedName { line: 1, name: "ADD".into() }),
+            ("CONST HALT 1\nHALT", ParseError::ReservedName { line: 1, name: "HALT".into() }),
+            ("CONST START 1\nHALT", ParseError::ReservedName { line: 1, name: "START".into() }),
+            ("CONST SIZE 1\nCONST SIZE 2", ParseError::RepeatingConstant { line: 2, name: "SIZE".into() }),
+        ];
+
+        for (source, error) in cases {
+            assert_eq!(Parser::default().parse(source).unwrap_err(), error, "source: {source:?}");
+        }
+
+        assert_eq!(parse_operands("CONST SIZE 4\nLOAD =SIZE"), [(OpCode::LOAD, OpType::Value, 4)]);
+    }
+
+    #[test]
+    fn jump_targets_are_reachable() {
+        // Instructions the jumps point to, and the ones following them, are reachable
+        let cases = [
+            ("JUMP =2\nHALT\nWRITE =5\nHALT", [(1, 2)].as_slice()),
+            ("JUMP 3\nHALT\nWRITE =5\nHALT", &[(1, 2), (2, 3)]),
+            ("JUMP =1\nWRITE =5\nHALT", &[]),
+            ("LOAD =1\nJGTZ 3\nHALT\nWRITE 0\nHALT", &[]),
+            ("LOAD =1 | JUMP ~2 | HALT | WRITE 0 | HALT", &[(2, 1)]),
+            ("LOAD =1\nJGTZ ~2\nHALT\nWRITE 0\nJUMP ~-4", &[]),
+        ];
+
+        for (source, unreachable) in cases {
+            let output = Parser::default().parse(source).unwrap();
+            let warnings: Vec<Warning> = unreachable
+                .iter()
+                .map(|&(index, line)| Warning::UnreachableInstruction { index, line })
+                .collect();
+            assert_eq!(output.warnings, warnings, "source: {source:?}");
+        }
+    }
+
+    #[test]
+    fn start_directive() {
+        let source = "START begin\ndouble:\nADD 0\nWRITE 0\nHALT\nbegin:\nREAD 0\nJUMP double";
+        let parsed = Parser::default().parse(source).unwrap();
+        assert_eq!(parsed.entry_point, 3);
+        assert_eq!(parsed.warnings, []);
+
+        let report = crate::run::run_checked(source, &[21]).unwrap();
+        assert_eq!((report.output, report.error), (vec![42], None));
+    }
+
+    #[test]
+    fn program_without_instructions() {
+        let source = "; A program made only of comments and labels\n# nothing to execute\nend:";
+        let parsed = Parser::default().parse(source).unwrap();
+        assert_eq!(parsed.instructions, []);
+        assert_eq!(parsed.label_map.get("end"), Some(&0));
+
+        let report = crate::run::run_checked(source, &[]).unwrap();
+        assert_eq!((report.output, report.steps, report.error), (vec![], 0, None));
+    }
+
+    #[test]
+    fn source_after_end_is_ignored() {
+        let source = "READ 0\nMULT =2\nWRITE 0\nEND\n\nScratch notes: 21 -> 42";
+        let parsed = Parser::default().parse(source).unwrap();
+        assert_eq!(parsed.instructions.len(), 3);
+        assert_eq!(parsed.warnings, [Warning::IgnoredAfterEnd { line: 4 }]);
+
+        let parsed = Parser::default().parse("HALT\nEND\n\n").unwrap();
+        assert_eq!(parsed.warnings, []);
+
+        let report = crate::run::run_checked(source, &[21]).unwrap();
+        assert_eq!((report.output, report.error), (vec![42], None));
+    }
+
+    #[test]
+    fn statements_separated_with_bar() {
+        let source = "READ 1 | LOAD 1 | MULT 1 | WRITE 0 | HALT ; square | of the input";
+        let parsed = Parser::default().parse(source).unwrap();
+        assert_eq!(parsed.source_lines, [1, 1, 1, 1, 1]);
+        assert_eq!(parsed.instructions[4].comment(), Some("square | of the input"));
+
+        let lines = "READ 1\nLOAD 1\nMULT 1\nWRITE 0\nHALT";
+        assert_eq!(parse_operands(source), parse_operands(lines));
+
+        let report = crate::run::run_checked(source, &[7]).unwrap();
+        assert_eq!((report.output, report.error), (vec![49], None));
+    }
+
+    #[test]
+    fn operand_types_are_checked() {
+        let invalid = [
+            "SWAP =1", "SWAP", "SWAP ~1", "STORE =1", "READ =1", "HALT 1", "HALT =1", "PUSH 1", "ADD",
+            "LOAD ~1", "SET =1, 2", "SET 1, ~2", "SET 1", "SWAP label\nlabel: HALT",
+        ];
+        for source in invalid {
+            let error = Parser::default().parse(source).unwrap_err();
+            assert!(matches!(error, ParseError::InvalidOperand { line: 1, .. }), "source: {source:?}, error: {error:?}");
+        }
+
+        let valid = ["SWAP 1", "SWAP *1", "SWAP @1", "STORE 1", "HALT", "ADD =1", "JUMP ~0", "SET 1, *2"];
+        for source in valid {
+            assert!(Parser::default().parse(source).is_ok(), "source: {source:?}");
+        }
+    }
+
+    #[test]
+    fn document_skips_statements_with_errors() {
+        let source = "start: LOAD =1\nbad: FOO 1\nloop: ; comment\nalso: STORE =1 | HALT";
+        let document = Parser::default().parse_document(source);
+
+        assert_eq!(document.items, [
+            DocumentItem::Label { line: 1, name: "start".into() },
+            DocumentItem::Instruction { line: 1, index: 0 },
+            DocumentItem::Label { line: 3, name: "loop".into() },
+            DocumentItem::Comment { line: 3, text: "; comment".into() },
+            DocumentItem::Instruction { line: 4, index: 1 },
+        ]);
+        assert_eq!(document.errors.len(), 2);
+    }
+}
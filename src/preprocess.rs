@@ -0,0 +1,159 @@
+use std::path::{Path, PathBuf};
+
+use crate::{source_lines, COMMENT_CHARS};
+
+/// Maximum nesting of the `INCLUDE` directives, protects against files including each other
+pub const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Errors that can occur while expanding the `INCLUDE` directives
+#[derive(Debug, PartialEq, Eq)]
+pub enum IncludeError {
+    /// File could not be read
+    Io { path: PathBuf, message: String },
+    /// `INCLUDE` directive in `line` of the file is not followed by a quoted path
+    InvalidDirective { path: PathBuf, line: usize },
+    /// Includes are nested deeper than [`MAX_INCLUDE_DEPTH`], most likely because of a cycle
+    DepthExceeded { path: PathBuf },
+}
+
+impl std::fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IncludeError::Io { path, message } => {
+                write!(f, "ERROR: Could not read `{}`: {message}", path.display())
+            }
+            IncludeError::InvalidDirective { path, line } => {
+                write!(f, "ERROR: Exception in line {line} of `{}`. INCLUDE directive requires a path in quotes.", path.display())
+            }
+            IncludeError::DepthExceeded { path } => {
+                write!(f, "ERROR: Includes nested too deeply in `{}`. Files most likely include each other.", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for IncludeError {}
+
+/// Place in the original files a line of the preprocessed source comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: PathBuf,
+    /// Line number, starting from 1
+    pub line: usize,
+}
+
+/// Source with all of the `INCLUDE` directives expanded
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessed {
+    /// Source that can be given to the parser
+    pub source: String,
+    /// Origin of every line of the source
+    pub locations: Vec<SourceLocation>,
+}
+
+impl Preprocessed {
+    /// Original location of the line of the preprocessed source, lines start from 1
+    pub fn location(&self, line: usize) -> Option<&SourceLocation> {
+        self.locations.get(line.checked_sub(1)?)
+    }
+
+    /// Returns `true` when lines come from more than one file
+    pub fn has_includes(&self) -> bool {
+        self.locations.windows(2).any(|pair| pair[0].file != pair[1].file)
+    }
+}
+
+/// Returns the path of the `INCLUDE "path"` directive, `Some(None)` when the directive is invalid
+/// and `None` when the line is not a directive at all
+fn include_path(line: &str) -> Option<Option<&str>> {
+    let code = match line.find(COMMENT_CHARS) {
+        Some(comment_start) => &line[..comment_start],
+        None => line,
+    };
+
+    let argument = code.trim().strip_prefix("INCLUDE")?;
+    if !argument.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let path = argument.trim().strip_prefix('"').and_then(|rest| rest.strip_suffix('"'));
+    Some(path.filter(|path| !path.is_empty()))
+}
+
+fn expand(path: &Path, depth: usize, output: &mut Preprocessed) -> Result<(), IncludeError> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(IncludeError::DepthExceeded { path: path.to_path_buf() });
+    }
+
+    let source = std::fs::read_to_string(path).map_err(|error| IncludeError::Io {
+        path: path.to_path_buf(),
+        message: error.to_string(),
+    })?;
+
+    for (line, text) in (1..).zip(source_lines(&source)) {
+        match include_path(text) {
+            Some(Some(included)) => {
+                // Paths are resolved relative to the including file
+                let included = path.parent().unwrap_or(Path::new("")).join(included);
+                expand(&included, depth + 1, output)?;
+            }
+            Some(None) => {
+                return Err(IncludeError::InvalidDirective { path: path.to_path_buf(), line });
+            }
+            None => {
+                output.source.push_str(text);
+                output.source.push('\n');
+                output.locations.push(SourceLocation { file: path.to_path_buf(), line });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the file and replaces every `INCLUDE "file.ram"` line with the contents of that file.
+/// Keeps track of where each line came from, see [`Preprocessed::location`]
+pub fn preprocess_with_locations(entry: &Path) -> Result<Preprocessed, IncludeError> {
+    let mut output = Preprocessed::default();
+    expand(entry, 0, &mut output)?;
+    Ok(output)
+}
+
+/// Reads the file and replaces every `INCLUDE "file.ram"` line with the contents of that file.
+/// Included paths are resolved relative to the including file
+pub fn preprocess(entry: &Path) -> Result<String, IncludeError> {
+    preprocess_with_locations(entry).map(|output| output.source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn includes_relative_to_the_including_file() {
+        let dir = std::env::temp_dir().join(format!("ramulator-include-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("include")).unwrap();
+        let entry = dir.join("main.ram");
+        let included = dir.join("include").join("double.ram");
+        std::fs::write(&entry, "LOAD =21\nINCLUDE \"include/double.ram\"\nWRITE 0\nHALT\n").unwrap();
+        std::fs::write(&included, "MULT =2\n").unwrap();
+
+        let output = preprocess_with_locations(&entry);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let output = output.unwrap();
+
+        assert_eq!(output.source, "LOAD =21\nMULT =2\nWRITE 0\nHALT\n");
+        assert!(output.has_includes());
+        assert_eq!(output.location(2), Some(&SourceLocation { file: included, line: 1 }));
+        assert_eq!(output.location(3), Some(&SourceLocation { file: entry, line: 3 }));
+    }
+
+    #[test]
+    fn include_directive_requires_quoted_path() {
+        assert_eq!(include_path("INCLUDE \"lib.ram\" ; helpers"), Some(Some("lib.ram")));
+        assert_eq!(include_path("INCLUDE lib.ram"), Some(None));
+        assert_eq!(include_path("INCLUDE \"\""), Some(None));
+        assert_eq!(include_path("INCLUDED \"lib.ram\""), None);
+        assert_eq!(include_path("; INCLUDE \"lib.ram\""), None);
+    }
+}
@@ -1,11 +1,198 @@
+use alloc::{
+    boxed::Box, collections::{BTreeMap, BTreeSet}, format, rc::Rc, string::{String, ToString}, vec, vec::Vec,
+};
+use core::ops::ControlFlow;
+
 use crate::{Instruction, OpType, OpCode};
+use crate::io::{InputSource, OutputFormat, OutputSink, Separator, VecInput};
+use crate::trace::TraceEntry;
+use crate::timeline::{Recording, StackChange, StepDiff};
+use crate::parser::{validate_jump_targets, ParseError};
 
 /// Data that is held by a register
 pub type RegisterData = i32;
 
-/// Register used as an input and output to store and load data from executed instructions
+/// Default register used as an input and output to store and load data from executed instructions.
+///
+/// The adder is an ordinary register, so it can be used as an operand like any other register:
+/// `ADD 0` doubles the adder, `STORE 0` leaves it unchanged and `SWAP 0` does nothing. Every
+/// instruction reads its operand before it modifies the adder.
 pub const ADDER: usize = 0;
 
+/// Executes an extension instruction, registered with [`RAM::register_extension`]. The instruction
+/// pointer is already advanced when the handler is called
+pub type ExtensionHandler = Box<dyn Fn(&mut RAM, &Instruction)>;
+
+/// Handler shared between the machine and the instruction being executed by it
+type SharedHandler = Rc<dyn Fn(&mut RAM, &Instruction)>;
+
+/// Extension instructions of the machine, by their mnemonic
+#[derive(Default)]
+struct Extensions(BTreeMap<String, SharedHandler>);
+
+impl core::fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+/// Instruction executed by [`RAM::step`] together with its effect on the machine. Also passed to
+/// the callback set with [`RAM::set_step_callback`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepInfo {
+    /// Number of the step, starting from 1
+    pub step: usize,
+    /// Executed instruction
+    pub instruction: Instruction,
+    /// Instruction pointer before the step, the index of the executed instruction
+    pub previous_pointer: usize,
+    /// Instruction pointer after the step
+    pub new_pointer: usize,
+    /// Value of the adder after the instruction was executed
+    pub accumulator: RegisterData,
+    /// Registers written by the instruction together with their new values, in the order of
+    /// writing
+    pub changed_registers: Vec<(usize, RegisterData)>,
+}
+
+/// Called after every executed instruction, see [`RAM::set_step_callback`]. Returning
+/// [`ControlFlow::Break`] halts the machine
+pub type StepCallback = Box<dyn FnMut(&StepInfo) -> ControlFlow<()>>;
+
+/// Callback set with [`RAM::set_step_callback`], if any
+#[derive(Default)]
+struct StepCallbackSlot(Option<StepCallback>);
+
+impl core::fmt::Debug for StepCallbackSlot {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let state = if self.0.is_some() { "Some(..)" } else { "None" };
+        f.write_str(state)
+    }
+}
+
+/// Errors that can occur while executing instructions
+#[derive(Debug, PartialEq, Eq)]
+pub enum RuntimeError {
+    /// Data passed to the `READ` instruction is not a valid 32 bit integer literal. `got` is the
+    /// trimmed text that was read and `index` is the position of the value in the input, counting
+    /// from 0
+    BadInput { got: String, index: usize },
+    /// The `READ` instruction executed in the given step has no more input data to read
+    UnexpectedEof { step: usize },
+    /// Instruction at `index` has an argument of incorrect type, for example `STORE =1`
+    InvalidOperand { opcode: OpCode, index: usize },
+    /// Register index used by an instruction is negative, for example `STORE *1` when register 1
+    /// holds -1
+    NegativeRegisterIndex { index: RegisterData },
+    /// Register index used by an instruction is above the limit set with [`RAM::set_max_registers`]
+    RegisterLimitExceeded { index: usize },
+    /// The `WRITE` instruction was executed more times than allowed by [`RAM::set_max_output`]
+    OutputLimitExceeded,
+    /// The `POP` instruction was executed with an empty stack
+    StackUnderflow,
+    /// The machine executed more instructions than allowed by [`RAM::set_max_steps`]
+    StepLimitExceeded { steps: usize },
+    /// Register at `index` was read before anything was written to it. Reported only when
+    /// [`UninitPolicy::Error`] is set
+    UninitializedRegister { index: usize },
+    /// Jump instruction points to an instruction that does not exist, for example `JUMP *1`
+    /// when register 1 holds -1
+    JumpOutOfRange { target: RegisterData },
+    /// Accessing the register at `index` would allocate more register cells than allowed by
+    /// [`RAM::set_max_memory_cells`]
+    MemoryLimitExceeded { index: usize },
+    /// Extension instruction has no handler registered with [`RAM::register_extension`]
+    UnknownExtension { name: String },
+    /// The machine ran out of instructions without executing `HALT`. Reported only when
+    /// [`RAM::set_require_halt`] is enabled
+    FellOffEnd,
+    /// The `DIV` instruction was executed with a divisor equal to 0
+    DivisionByZero,
+    /// The `RAND` instruction was given a bound that is not positive, for example `RAND =0`
+    InvalidRandomBound { bound: RegisterData },
+    /// `error` occurred while executing an instruction parsed from `source` in `line`
+    InInstruction { line: usize, source: String, error: Box<RuntimeError> },
+}
+
+impl core::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RuntimeError::BadInput { got, index } => {
+                write!(f, "ERROR: Incorrect READ data: Input value at index {index} must be a 32 bit integer, got `{got}`")
+            }
+            RuntimeError::UnexpectedEof { step } => {
+                write!(f, "ERROR: Unexpected end of input: READ instruction in step {step} has no more data to read")
+            }
+            RuntimeError::InvalidOperand { opcode, index } => {
+                write!(f, "ERROR: Invalid operand: Instruction {opcode:?} (number {index}) has an argument of incorrect type")
+            }
+            RuntimeError::NegativeRegisterIndex { index } => {
+                write!(f, "ERROR: Negative register index: Register {index} does not exist")
+            }
+            RuntimeError::RegisterLimitExceeded { index } => {
+                write!(f, "ERROR: Register limit exceeded: Register {index} is above the allowed limit")
+            }
+            RuntimeError::OutputLimitExceeded => {
+                write!(f, "ERROR: Output limit exceeded: Program wrote more values than allowed")
+            }
+            RuntimeError::StackUnderflow => {
+                write!(f, "ERROR: Stack underflow: POP instruction was executed with an empty stack")
+            }
+            RuntimeError::StepLimitExceeded { steps } => {
+                write!(f, "ERROR: Step limit exceeded: Program did not finish in {steps} steps")
+            }
+            RuntimeError::UninitializedRegister { index } => {
+                write!(f, "ERROR: Uninitialized register: Register {index} was read before being written")
+            }
+            RuntimeError::JumpOutOfRange { target } => {
+                write!(f, "ERROR: Jump out of range: Instruction {target} does not exist")
+            }
+            RuntimeError::MemoryLimitExceeded { index } => {
+                write!(f, "ERROR: Memory limit exceeded: Register {index} would need more memory cells than allowed")
+            }
+            RuntimeError::UnknownExtension { name } => {
+                write!(f, "ERROR: Unknown extension: Instruction `{name}` has no registered handler")
+            }
+            RuntimeError::FellOffEnd => {
+                write!(f, "ERROR: Missing HALT: The program ended without executing HALT")
+            }
+            RuntimeError::DivisionByZero => {
+                write!(f, "ERROR: Division by zero: DIV cannot divide by 0")
+            }
+            RuntimeError::InvalidRandomBound { bound } => {
+                write!(f, "ERROR: Invalid random bound: RAND needs a positive bound, got {bound}")
+            }
+            RuntimeError::InInstruction { line, source, error } => {
+                write!(f, "{error} (while executing `{source}` at line {line})")
+            }
+        }
+    }
+}
+
+impl core::error::Error for RuntimeError {}
+
+impl RuntimeError {
+    /// The error itself, without the information about the instruction that caused it
+    pub fn root(&self) -> &RuntimeError {
+        match self {
+            RuntimeError::InInstruction { error, .. } => error.root(),
+            error => error,
+        }
+    }
+}
+
+/// Attaches the source line of the instruction to the error, if the instruction has one
+fn locate_error(error: RuntimeError, inst: &Instruction) -> RuntimeError {
+    match inst.source() {
+        Some(source) => RuntimeError::InInstruction {
+            line: source.line,
+            source: source.text.clone(),
+            error: Box::new(error),
+        },
+        None => error,
+    }
+}
+
 /// Random Access Machine 
 ///
 /// Responsible for executing RAM instructions, holds current state of the machine and its data 
@@ -14,61 +201,1041 @@ pub struct RAM {
     /// State of the machine, set to `true` when the `HALT` is reached or the machine runs
     /// out of instructions to execute
     finished: bool,
-    /// Stack of instructions that get executed by the machine
-    instruction_stack: Vec<Instruction>,
+    /// Stack of instructions that get executed by the machine. Shared, so that [`RAM::run`] can
+    /// borrow the instructions while the rest of the machine is modified
+    instruction_stack: Rc<[Instruction]>,
     /// Points to the current instruction from the instruction stack
     instruction_pointer: usize,
     /// Registers that store data of the machine, register 0 is considered to be an adder that is
     /// used to store results of executed instructions
     registers: Vec<RegisterData>,
+    /// Marks registers that were read or written by the executed instructions
+    used_registers: Vec<bool>,
+    /// Values pushed with the `PUSH` instruction, the top of the stack is the last value
+    stack: Vec<RegisterData>,
+    /// Marks registers that were written by the executed instructions
+    written_registers: Vec<bool>,
+    /// Behaviour of reads from registers that were never written
+    uninitialized_policy: UninitPolicy,
+    /// Index of the register used as the adder, [`ADDER`] by default
+    adder: usize,
+    /// Index of the instruction the execution begins at
+    entry_point: usize,
+    /// Number of registers the machine is allowed to use, unlimited when `None`
+    max_registers: Option<usize>,
+    /// Number of register cells the machine is allowed to allocate, unlimited when `None`
+    max_memory_cells: Option<usize>,
+    /// Executed instructions, recorded only when enabled with [`RAM::enable_trace`]
+    trace: Option<Vec<TraceEntry>>,
+    /// Values of the adder after every executed instruction, recorded only when enabled with
+    /// [`RAM::enable_accumulator_history`]
+    accumulator_history: Option<Vec<RegisterData>>,
+    /// Every state of the machine, recorded only when enabled with [`RAM::enable_recording`]
+    recording: Option<Recording>,
+    /// Registers written by the currently executed instruction, collected for the trace and the
+    /// recording
+    recorded_changes: Vec<(usize, RegisterData)>,
+    /// Change of the stack made by the currently executed instruction, collected for the recording
+    recorded_stack_change: StackChange,
+    /// Instruction indices at which a debugger should pause before executing them
+    breakpoints: BTreeSet<usize>,
+    /// Labels of the loaded program and indices of the instructions they point to, see
+    /// [`RAM::set_labels`]
+    labels: BTreeMap<String, usize>,
+    /// Registers whose changes pause the execution in [`RAM::run_steps`]
+    watched_registers: BTreeSet<usize>,
+    /// Change of a watched register made by the last executed instruction
+    watchpoint_hit: Option<StepOutcome>,
+    /// Number of instructions executed since the machine was started
+    steps: usize,
+    /// Costs of the instructions that differ from the default cost of 1
+    opcode_costs: BTreeMap<OpCode, usize>,
+    /// Sum of the costs of the instructions executed since the machine was started
+    weighted_cost: usize,
+    /// Maximum number of values the machine is allowed to write, unlimited when `None`
+    max_output: Option<usize>,
+    /// Maximum number of instructions the machine is allowed to execute, unlimited when `None`
+    max_steps: Option<usize>,
+    /// Number of instructions after which `step_warning_reached` is set, see
+    /// [`RAM::set_step_warning`]
+    step_warning: Option<usize>,
+    /// Set once the machine executed the number of instructions given to
+    /// [`RAM::set_step_warning`]
+    step_warning_reached: bool,
+    /// Running out of instructions without executing `HALT` is an error when set
+    require_halt: bool,
+    /// The `CLEAR` instruction leaves the adder register as it is when set
+    clear_keeps_adder: bool,
+    /// Arithmetic results that wrap around set `overflow_occurred` when set
+    track_overflow: bool,
+    /// Set when an arithmetic instruction wrapped around since the machine was started, see
+    /// [`RAM::set_overflow_tracking`]
+    overflow_occurred: bool,
+    /// Number of values written since the machine was started
+    output_count: usize,
+    /// Number of values read since the machine was started
+    input_count: usize,
+    /// Source of the values read by the `READ` instruction
+    input: Box<dyn InputSource>,
+    /// Destination of the values written by the `WRITE` instruction
+    output: Box<dyn OutputSink>,
+    /// Format of the values written by the `WRITE` instruction
+    output_format: OutputFormat,
+    /// What is written between the values written by the `WRITE` instruction
+    output_separator: Separator,
+    /// Set when values were written to the current line of the output, but the line did not end
+    output_line_open: bool,
+    /// Handlers of the extension instructions
+    extensions: Extensions,
+    /// Called after every executed instruction
+    step_callback: StepCallbackSlot,
+    /// Seed of the random number generator used by the `RAND` instruction, 0 by default
+    seed: u64,
+    /// Current state of the random number generator
+    rng_state: u64,
+}
+
+/// Snapshot of the machine state, can be used to pause the execution and resume it later
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MachineState {
+    pub instruction_pointer: usize,
+    pub registers: Vec<RegisterData>,
+    pub finished: bool,
+    pub steps: usize,
+    /// Values pushed with the `PUSH` instruction, the top of the stack is the last value
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub stack: Vec<RegisterData>,
+    /// State of the random number generator used by the `RAND` instruction
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub rng_state: u64,
+}
+
+impl core::fmt::Display for RAM {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let registers = self.dump_registers();
+        let registers = if registers.is_empty() { "all zero" } else { registers.as_str() };
+
+        writeln!(f, "Instruction pointer: {}", self.instruction_pointer)?;
+        writeln!(f, "Finished:            {}", self.finished)?;
+        writeln!(f, "Steps:               {}", self.steps)?;
+        writeln!(f, "Registers:           {registers}")?;
+        if self.stack.is_empty() {
+            write!(f, "Stack:               empty")
+        } else {
+            write!(f, "Stack:               {:?}", self.stack)
+        }
+    }
+}
+
+/// Iterator over instructions executed by the machine, created with [`RAM::steps`]
+#[derive(Debug)]
+pub struct StepIter<'a> {
+    ram: &'a mut RAM,
+    /// Set after a runtime error, the machine cannot continue after it
+    failed: bool,
+}
+
+impl Iterator for StepIter<'_> {
+    type Item = Result<Instruction, RuntimeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let result = self.ram.execute_next_instruction();
+        self.failed = result.is_err();
+        result.transpose()
+    }
+}
+
+/// Space and time summary of the program execution, returned by [`RAM::stats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MachineStats {
+    /// Number of loaded instructions
+    pub instruction_count: usize,
+    /// Highest index of a register read or written by the program, `None` if none was used
+    pub highest_register: Option<usize>,
+    /// Number of distinct registers read or written by the program
+    pub distinct_registers: usize,
+    /// Number of executed instructions
+    pub steps: usize,
+    /// Sum of the costs of the executed instructions, see [`RAM::set_opcode_cost`]
+    pub weighted_cost: usize,
+}
+
+/// Behaviour of the machine when a register that was never written is read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UninitPolicy {
+    /// Registers that were never written hold 0
+    #[default]
+    Zero,
+    /// Reading a register that was never written results in [`RuntimeError::UninitializedRegister`]
+    Error,
+}
+
+/// Reason why [`RAM::run_steps`] stopped executing instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// The machine has finished executing
+    Halted,
+    /// The next instruction is a breakpoint
+    Breakpoint,
+    /// All of the fuel was used up, but the machine is still running
+    OutOfFuel,
+    /// The last executed instruction changed the value of a watched register
+    WatchpointHit { register: usize, old: RegisterData, new: RegisterData },
+}
+
+/// Configures a virtual machine step by step, see [`RAM::builder`].
+///
+/// Settings that are not given keep their defaults, the same as in [`RAM::new`].
+#[derive(Debug, Default)]
+pub struct RamBuilder {
+    ram: RAM,
+}
+
+impl RamBuilder {
+    /// Source of the values read by the `READ` instruction
+    pub fn input(mut self, input: Box<dyn InputSource>) -> Self {
+        self.ram.set_input(input);
+        self
+    }
+
+    /// Destination of the values written by the `WRITE` instruction
+    pub fn output(mut self, output: Box<dyn OutputSink>) -> Self {
+        self.ram.set_output(output);
+        self
+    }
+
+    /// Format of the values written by the `WRITE` instruction
+    pub fn output_format(mut self, format: OutputFormat) -> Self {
+        self.ram.set_output_format(format);
+        self
+    }
+
+    /// What is written between the values written by the `WRITE` instruction
+    pub fn output_separator(mut self, separator: Separator) -> Self {
+        self.ram.set_output_separator(separator);
+        self
+    }
+
+    /// Index of the register used as the adder
+    pub fn adder(mut self, adder: usize) -> Self {
+        self.ram.adder = adder;
+        self
+    }
+
+    /// Number of registers the machine is allowed to use
+    pub fn max_registers(mut self, n: usize) -> Self {
+        self.ram.set_max_registers(n);
+        self
+    }
+
+    /// Number of register cells the machine is allowed to allocate
+    pub fn max_memory_cells(mut self, n: usize) -> Self {
+        self.ram.set_max_memory_cells(n);
+        self
+    }
+
+    /// Number of instructions the machine is allowed to execute
+    pub fn max_steps(mut self, n: usize) -> Self {
+        self.ram.set_max_steps(n);
+        self
+    }
+
+    /// Makes running out of instructions without `HALT` an error, see [`RAM::set_require_halt`]
+    pub fn require_halt(mut self) -> Self {
+        self.ram.set_require_halt(true);
+        self
+    }
+
+    /// Makes the `CLEAR` instruction keep the adder, see [`RAM::set_clear_keeps_adder`]
+    pub fn clear_keeps_adder(mut self) -> Self {
+        self.ram.set_clear_keeps_adder(true);
+        self
+    }
+
+    /// Notes arithmetic results that wrap around, see [`RAM::set_overflow_tracking`]
+    pub fn overflow_tracking(mut self) -> Self {
+        self.ram.set_overflow_tracking(true);
+        self
+    }
+
+    /// Number of instructions after which the machine warns that the program may be looping, see
+    /// [`RAM::set_step_warning`]
+    pub fn step_warning(mut self, n: usize) -> Self {
+        self.ram.set_step_warning(n);
+        self
+    }
+
+    /// Number of values the machine is allowed to write
+    pub fn max_output(mut self, n: usize) -> Self {
+        self.ram.set_max_output(n);
+        self
+    }
+
+    /// Behaviour of reads from registers that were never written
+    pub fn uninitialized_policy(mut self, policy: UninitPolicy) -> Self {
+        self.ram.set_uninitialized_policy(policy);
+        self
+    }
+
+    /// Records every executed instruction, see [`RAM::enable_trace`]
+    pub fn trace(mut self) -> Self {
+        self.ram.enable_trace();
+        self
+    }
+
+    /// Records the value of the adder after every step, see [`RAM::enable_accumulator_history`]
+    pub fn accumulator_history(mut self) -> Self {
+        self.ram.enable_accumulator_history();
+        self
+    }
+
+    /// Records every state of the machine, see [`RAM::enable_recording`]
+    pub fn recording(mut self) -> Self {
+        self.ram.enable_recording();
+        self
+    }
+
+    /// Cost of executing a single instruction with the given opcode, see [`RAM::set_opcode_cost`]
+    pub fn opcode_cost(mut self, opcode: OpCode, cost: usize) -> Self {
+        self.ram.set_opcode_cost(opcode, cost);
+        self
+    }
+
+    /// Marks the instruction at `idx` as a breakpoint
+    pub fn breakpoint(mut self, idx: usize) -> Self {
+        self.ram.add_breakpoint(idx);
+        self
+    }
+
+    /// Watches the register at `idx`, see [`RAM::watch_register`]
+    pub fn watch_register(mut self, idx: usize) -> Self {
+        self.ram.watch_register(idx);
+        self
+    }
+
+    /// Called after every executed instruction, see [`RAM::set_step_callback`]
+    pub fn step_callback(mut self, callback: StepCallback) -> Self {
+        self.ram.set_step_callback(callback);
+        self
+    }
+
+    /// Seed of the random number generator, see [`RAM::set_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.ram.set_seed(seed);
+        self
+    }
+
+    /// Executes the extension instruction `name` with the handler, see [`RAM::register_extension`]
+    pub fn extension(mut self, name: &str, handler: ExtensionHandler) -> Self {
+        self.ram.register_extension(name, handler);
+        self
+    }
+
+    /// Creates the configured virtual machine
+    pub fn build(self) -> RAM {
+        self.ram
+    }
 }
 
 impl RAM {
+    /// Creates a builder that configures a new virtual machine, for example:
+    ///
+    /// ```rust
+    /// use RAMulator::{io::VecInput, ram::RAM};
+    ///
+    /// let ram = RAM::builder()
+    ///     .input(Box::new(VecInput::new(&[1, 2, 3])))
+    ///     .max_steps(10_000)
+    ///     .build();
+    /// ```
+    pub fn builder() -> RamBuilder {
+        RamBuilder::default()
+    }
+
     /// Creates a new virtual machine
     pub fn new() -> Self {
         Self::default()
     }
 
-    pub fn load_instructions(&mut self, instructions: Vec<Instruction>) {
-        self.instruction_stack = instructions;
+    /// Creates a new virtual machine that reads the given values instead of the standard input
+    pub fn with_input_slice(values: &[RegisterData]) -> Self {
+        let mut ram = Self::default();
+        ram.set_input(Box::new(VecInput::new(values)));
+        ram
+    }
+
+    /// Creates a new virtual machine that uses register `adder` as the adder. This allows to keep
+    /// register 0 as a general purpose register
+    pub fn with_adder(adder: usize) -> Self {
+        Self { adder, ..Self::default() }
+    }
+
+    /// Index of the register used as the adder
+    pub fn adder(&self) -> usize {
+        self.adder
+    }
+
+    /// Loads instructions into the machine.
+    ///
+    /// Instructions may come from outside of the parser, so jump instructions with a literal
+    /// argument are checked to point inside of the instruction stack. On failure the previously
+    /// loaded instructions are kept.
+    pub fn load_instructions(&mut self, instructions: Vec<Instruction>) -> Result<(), ParseError> {
+        validate_jump_targets(&instructions)?;
+        self.instruction_stack = instructions.into();
+        self.labels.clear();
+        Ok(())
+    }
+
+    /// Sets the index of the instruction the execution begins at. The instruction pointer is
+    /// moved there as well, so this should be called before the execution starts
+    pub fn set_entry_point(&mut self, idx: usize) {
+        self.entry_point = idx;
+        self.instruction_pointer = idx;
+    }
+
+    /// Brings the machine back to its initial state, keeping loaded instructions and breakpoints
+    pub fn reset(&mut self) {
+        self.end_output_line();
+        self.finished = false;
+        self.instruction_pointer = self.entry_point;
+        self.registers.clear();
+        self.used_registers.clear();
+        self.stack.clear();
+        self.written_registers.clear();
+        self.steps = 0;
+        self.weighted_cost = 0;
+        self.rng_state = self.seed;
+        self.watchpoint_hit = None;
+        if let Some(trace) = &mut self.trace {
+            trace.clear();
+        }
+        if let Some(history) = &mut self.accumulator_history {
+            history.clear();
+        }
+        if self.recording.is_some() {
+            self.recording = Some(Recording::new(self.snapshot()));
+        }
+        self.output_count = 0;
+        self.input_count = 0;
+        self.overflow_occurred = false;
+        self.step_warning_reached = false;
+    }
+
+    /// Sets the source of the values read by the `READ` instruction, standard input is used by
+    /// default
+    pub fn set_input(&mut self, input: Box<dyn InputSource>) {
+        self.input = input;
+    }
+
+    /// Sets the destination of the values written by the `WRITE` instruction, standard output is
+    /// used by default
+    pub fn set_output(&mut self, output: Box<dyn OutputSink>) {
+        self.output = output;
+    }
+
+    /// Sets the format of the values written by the `WRITE` instruction
+    pub fn set_output_format(&mut self, format: OutputFormat) {
+        self.output_format = format;
+    }
+
+    /// Sets what is written between the values written by the `WRITE` instruction. By default
+    /// every value is written in its own line
+    pub fn set_output_separator(&mut self, separator: Separator) {
+        self.output_separator = separator;
+    }
+
+    /// Ends the line of values written so far, so that the output does not end in the middle of
+    /// a line
+    fn end_output_line(&mut self) {
+        if core::mem::take(&mut self.output_line_open) {
+            self.output.flush_line();
+        }
+    }
+
+    /// Limits the number of registers to `n`, using a register with index `n` or higher results in
+    /// [`RuntimeError::RegisterLimitExceeded`]. Prevents huge allocations when a program uses a
+    /// large register index by accident
+    pub fn set_max_registers(&mut self, n: usize) {
+        self.max_registers = Some(n);
+    }
+
+    /// Limits the number of allocated register cells to `n`, exceeding the limit results in
+    /// [`RuntimeError::MemoryLimitExceeded`]. Registers are stored contiguously, so accessing
+    /// register 99 allocates cells for registers 0 to 99
+    pub fn set_max_memory_cells(&mut self, n: usize) {
+        self.max_memory_cells = Some(n);
+    }
+
+    /// Limits the number of executed `WRITE` instructions to `n`, exceeding the limit results in
+    /// [`RuntimeError::OutputLimitExceeded`]
+    pub fn set_max_output(&mut self, n: usize) {
+        self.max_output = Some(n);
+    }
+
+    /// Limits the number of executed instructions to `n`, exceeding the limit results in
+    /// [`RuntimeError::StepLimitExceeded`]. Protects against programs that never finish
+    pub fn set_max_steps(&mut self, n: usize) {
+        self.max_steps = Some(n);
+    }
+
+    /// Sets a soft limit of `n` executed instructions. Unlike [`RAM::set_max_steps`] the execution
+    /// goes on after reaching it, the machine only sets the flag returned by
+    /// [`RAM::step_warning_reached`], so that a long run can be reported as one that may be looping
+    pub fn set_step_warning(&mut self, n: usize) {
+        self.step_warning = Some(n);
+    }
+
+    /// Returns `true` once the machine executed at least as many instructions as given to
+    /// [`RAM::set_step_warning`]. The flag stays set until the machine is reset
+    pub fn step_warning_reached(&self) -> bool {
+        self.step_warning_reached
+    }
+
+    /// When `required` is set, running out of instructions without executing `HALT` (including a
+    /// jump right past the last instruction) results in [`RuntimeError::FellOffEnd`]. By default
+    /// such programs finish normally
+    pub fn set_require_halt(&mut self, required: bool) {
+        self.require_halt = required;
+    }
+
+    /// When `keep` is set, the `CLEAR` instruction sets every register except the adder to zero,
+    /// so that the result of the previous computation can be carried over. By default the adder is
+    /// cleared as well
+    pub fn set_clear_keeps_adder(&mut self, keep: bool) {
+        self.clear_keeps_adder = keep;
+    }
+
+    /// When `enabled`, an arithmetic instruction whose result does not fit in 32 bits and wraps
+    /// around sets a flag that stays set until the machine is reset, see
+    /// [`RAM::overflow_occurred`]. Disabled by default
+    pub fn set_overflow_tracking(&mut self, enabled: bool) {
+        self.track_overflow = enabled;
+    }
+
+    /// Returns `true` when an arithmetic instruction wrapped around since the machine was started,
+    /// so the written results may be incorrect. Always `false` unless enabled with
+    /// [`RAM::set_overflow_tracking`]
+    pub fn overflow_occurred(&self) -> bool {
+        self.overflow_occurred
     }
 
-    fn get_register_data(&mut self, idx: usize) -> RegisterData {
+    /// Stores the result of an arithmetic instruction in the adder, noting whether it wrapped
+    /// around
+    fn set_arithmetic_result(&mut self, (data, overflowed): (RegisterData, bool)) {
+        if overflowed && self.track_overflow {
+            self.overflow_occurred = true;
+        }
+        self.set_register_data(self.adder, data);
+    }
+
+    /// Marks the machine as finished after it ran out of instructions, which is an error when
+    /// `HALT` is required
+    fn fall_off_end(&mut self) -> Result<(), RuntimeError> {
+        let was_finished = core::mem::replace(&mut self.finished, true);
+        if self.require_halt && !was_finished {
+            Err(RuntimeError::FellOffEnd)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sets what happens when a register that was never written is read, by default such
+    /// registers hold 0
+    pub fn set_uninitialized_policy(&mut self, policy: UninitPolicy) {
+        self.uninitialized_policy = policy;
+    }
+
+    /// Captures the current state of the machine. Loaded instructions are not part of the state
+    pub fn snapshot(&self) -> MachineState {
+        MachineState {
+            instruction_pointer: self.instruction_pointer,
+            registers: self.registers.clone(),
+            finished: self.finished,
+            steps: self.steps,
+            stack: self.stack.clone(),
+            rng_state: self.rng_state,
+        }
+    }
+
+    /// Brings the machine to a previously captured state
+    pub fn restore(&mut self, state: MachineState) {
+        self.instruction_pointer = state.instruction_pointer;
+        self.registers = state.registers;
+        self.used_registers.resize(self.registers.len(), false);
+        // The snapshot does not tell which registers were written, all of them are considered set
+        self.written_registers = vec![true; self.registers.len()];
+        self.finished = state.finished;
+        self.steps = state.steps;
+        self.stack = state.stack;
+        self.rng_state = state.rng_state;
+    }
+
+    /// Number of instructions executed since the machine was started
+    pub fn step_count(&self) -> usize {
+        self.steps
+    }
+
+    /// Sets the cost of executing a single instruction with the given opcode. Every instruction
+    /// costs 1 by default, which makes the weighted cost equal to the step count
+    pub fn set_opcode_cost(&mut self, opcode: OpCode, cost: usize) {
+        self.opcode_costs.insert(opcode, cost);
+    }
+
+    /// Seeds the random number generator used by the `RAND` instruction. The same seed always
+    /// gives the same values, so runs of the program can be reproduced. The generator starts over
+    /// from the seed when the machine is reset
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng_state = seed;
+    }
+
+    /// Next value of the random number generator, SplitMix64
+    fn next_random(&mut self) -> u64 {
+        self.rng_state = self.rng_state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Registers the handler executing the extension instruction `name`, for example `SQRT`.
+    ///
+    /// The parser accepts the mnemonic once it is allowed with
+    /// [`Parser::allow_extension`](crate::parser::Parser::allow_extension), see
+    /// [`RAM::extension_names`]. The handler has full access to the machine, including the loaded
+    /// instructions. Registering a handler under the same name again replaces it.
+    pub fn register_extension(&mut self, name: &str, handler: ExtensionHandler) {
+        self.extensions.0.insert(name.to_string(), Rc::from(handler));
+    }
+
+    /// Mnemonics of the registered extension instructions, sorted by name
+    pub fn extension_names(&self) -> impl Iterator<Item = &str> {
+        self.extensions.0.keys().map(String::as_str)
+    }
+
+    /// Sum of the costs of the instructions executed since the machine was started. Not part of
+    /// the [`MachineState`], so it is not brought back by [`RAM::restore`]
+    pub fn total_weighted_cost(&self) -> usize {
+        self.weighted_cost
+    }
+
+    /// Returns `true` when the machine has finished executing
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Index of the instruction that is going to be executed next
+    pub fn instruction_pointer(&self) -> usize {
+        self.instruction_pointer
+    }
+
+    /// Instructions loaded into the machine
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instruction_stack
+    }
+
+    /// Instruction that is going to be executed next, if there is any
+    pub fn current_instruction(&self) -> Option<&Instruction> {
+        self.instruction_stack.get(self.instruction_pointer)
+    }
+
+    /// Registers of the machine, including the adder
+    pub fn registers(&self) -> &[RegisterData] {
+        &self.registers
+    }
+
+    /// Window of `count` registers starting at index `start`, paired with their indices. Registers
+    /// that were never allocated hold zero, so any range can be shown, for example by a memory
+    /// inspector paging through the address space
+    pub fn memory_view(&self, start: usize, count: usize) -> Vec<(usize, RegisterData)> {
+        let end = start.saturating_add(count);
+        (start..end)
+            .map(|idx| (idx, self.registers.get(idx).copied().unwrap_or(0)))
+            .collect()
+    }
+
+    /// Writes the register the same way an instruction does, meant for extension handlers. The
+    /// change is seen by the trace, the recording and the watched registers
+    pub fn set_register(&mut self, idx: usize, data: RegisterData) {
+        self.set_register_data(idx, data);
+    }
+
+    /// Sets the callback called after every executed instruction with its step number, index and
+    /// the resulting value of the adder. Unlike the trace, nothing is stored by the machine. When
+    /// the callback returns [`ControlFlow::Break`], the machine halts as if it executed `HALT`
+    pub fn set_step_callback(&mut self, callback: StepCallback) {
+        self.step_callback.0 = Some(callback);
+    }
+
+    /// Removes the callback set with [`RAM::set_step_callback`]
+    pub fn clear_step_callback(&mut self) {
+        self.step_callback.0 = None;
+    }
+
+    /// Starts recording every executed instruction together with the value of the adder
+    pub fn enable_trace(&mut self) {
+        self.trace.get_or_insert_with(Vec::new);
+    }
+
+    /// Starts recording the value of the adder after every executed instruction. Cheaper than
+    /// the full trace when only the adder matters, for example to plot it
+    pub fn enable_accumulator_history(&mut self) {
+        self.accumulator_history.get_or_insert_with(Vec::new);
+    }
+
+    /// Values of the adder after every instruction executed since the history was enabled
+    pub fn accumulator_history(&self) -> &[RegisterData] {
+        self.accumulator_history.as_deref().unwrap_or_default()
+    }
+
+    /// Instructions executed since the trace was enabled
+    pub fn trace(&self) -> &[TraceEntry] {
+        self.trace.as_deref().unwrap_or_default()
+    }
+
+    /// Summary of the program size, memory used and time taken by the execution
+    pub fn stats(&self) -> MachineStats {
+        MachineStats {
+            instruction_count: self.instruction_stack.len(),
+            highest_register: self.used_registers.iter().rposition(|used| *used),
+            distinct_registers: self.used_registers.iter().filter(|used| **used).count(),
+            steps: self.steps,
+            weighted_cost: self.weighted_cost,
+        }
+    }
+
+    /// Formats registers holding a non-zero value, for example `R0=5 R3=-1`
+    pub fn dump_registers(&self) -> String {
+        self.format_registers(false)
+    }
+
+    /// Formats every register up to the highest one used, including the ones holding zero
+    pub fn dump_all_registers(&self) -> String {
+        self.format_registers(true)
+    }
+
+    fn format_registers(&self, include_zeros: bool) -> String {
+        self.registers
+            .iter()
+            .enumerate()
+            .filter(|(_, data)| include_zeros || **data != 0)
+            .map(|(idx, data)| format!("R{idx}={data}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Marks the instruction at `idx` as a breakpoint
+    pub fn add_breakpoint(&mut self, idx: usize) {
+        self.breakpoints.insert(idx);
+    }
+
+    /// Removes the breakpoint at `idx`, returns `false` if there was none
+    pub fn remove_breakpoint(&mut self, idx: usize) -> bool {
+        self.breakpoints.remove(&idx)
+    }
+
+    /// Sets the labels of the loaded program, usually the `label_map` of the parser, so that
+    /// breakpoints can be placed by the label name. Loading other instructions forgets them
+    pub fn set_labels(&mut self, labels: BTreeMap<String, usize>) {
+        self.labels = labels;
+    }
+
+    /// Index of the instruction the label points to, `None` when there is no such label
+    pub fn label_index(&self, name: &str) -> Option<usize> {
+        self.labels.get(name).copied()
+    }
+
+    /// Marks the instruction the label points to as a breakpoint, so that `loop` can be used
+    /// instead of the index of the instruction after `loop:`. Returns the index of the
+    /// instruction, `None` when there is no such label (see [`RAM::set_labels`])
+    pub fn add_breakpoint_label(&mut self, name: &str) -> Option<usize> {
+        let idx = self.label_index(name)?;
+        self.add_breakpoint(idx);
+        Some(idx)
+    }
+
+    /// Returns `true` when the instruction under the instruction pointer is a breakpoint
+    pub fn at_breakpoint(&self) -> bool {
+        self.breakpoints.contains(&self.instruction_pointer)
+    }
+
+    /// Describes the state of the machine as a JSON object, meant for automatic grading of
+    /// finished programs:
+    ///
+    /// `{"output":[3,5],"registers":{"0":5,"1":3},"steps":7,"halted":true}`
+    ///
+    /// The output is known only when it is written to a sink that keeps the values, such as
+    /// [`VecOutput`](crate::io::VecOutput), otherwise it is `null`. Registers are listed by their
+    /// index, only the non-zero ones.
+    pub fn state_json(&self) -> String {
+        let output = match self.output.captured_values() {
+            Some(values) => {
+                let values: Vec<String> = values.iter().map(RegisterData::to_string).collect();
+                format!("[{}]", values.join(","))
+            }
+            None => "null".to_string(),
+        };
+
+        let registers: Vec<String> = self.registers
+            .iter()
+            .enumerate()
+            .filter(|(_, data)| **data != 0)
+            .map(|(idx, data)| format!("\"{idx}\":{data}"))
+            .collect();
+
+        format!(
+            "{{\"output\":{output},\"registers\":{{{}}},\"steps\":{},\"halted\":{}}}",
+            registers.join(","),
+            self.steps,
+            self.finished,
+        )
+    }
+
+    /// Starts recording every state of the machine, so that [`RAM::goto_step`] can later bring
+    /// it back to any of the executed steps
+    pub fn enable_recording(&mut self) {
+        self.recording = Some(Recording::new(self.snapshot()));
+    }
+
+    /// First and last step the machine can be moved to with [`RAM::goto_step`], `None` when
+    /// recording is not enabled
+    pub fn recorded_steps(&self) -> Option<(usize, usize)> {
+        self.recording.as_ref().map(Recording::steps)
+    }
+
+    /// Brings the machine to the state right after `step` instructions were executed, forward or
+    /// backward. Returns `false` when the step was not recorded.
+    ///
+    /// Only the registers, instruction pointer and step count are restored. Values already read
+    /// from the input or written to the output are not given back. Executing an instruction after
+    /// going back drops the recorded steps after it.
+    pub fn goto_step(&mut self, step: usize) -> bool {
+        let Some(state) = self.recording.as_ref().and_then(|recording| recording.state_at(step)) else {
+            return false;
+        };
+
+        self.restore(state);
+        self.watchpoint_hit = None;
+        true
+    }
+
+    /// Watches the register at `idx`, the execution pauses whenever its value changes
+    pub fn watch_register(&mut self, idx: usize) {
+        self.watched_registers.insert(idx);
+    }
+
+    /// Stops watching the register at `idx`, returns `false` if it was not watched
+    pub fn unwatch_register(&mut self, idx: usize) -> bool {
+        self.watched_registers.remove(&idx)
+    }
+
+    /// Returns [`StepOutcome::WatchpointHit`] when the last executed instruction changed the value
+    /// of a watched register
+    pub fn watchpoint_hit(&self) -> Option<StepOutcome> {
+        self.watchpoint_hit
+    }
+
+    /// Returns an iterator that executes the next instruction on every iteration and yields it.
+    /// The iterator ends when the machine finishes or right after a runtime error
+    pub fn steps(&mut self) -> StepIter<'_> {
+        StepIter { ram: self, failed: false }
+    }
+
+    /// Executes at most `fuel` instructions and reports why the execution stopped.
+    ///
+    /// Allows to run long programs in small chunks, for example once per frame of an event loop.
+    /// The first instruction is always executed, even if it is a breakpoint, so that calling this
+    /// function again resumes the execution after hitting a breakpoint.
+    pub fn run_steps(&mut self, fuel: usize) -> Result<StepOutcome, RuntimeError> {
+        for executed in 0..fuel {
+            if executed > 0 && self.at_breakpoint() {
+                return Ok(StepOutcome::Breakpoint);
+            }
+
+            if self.execute_next_instruction()?.is_none() {
+                return Ok(StepOutcome::Halted);
+            }
+
+            if let Some(hit) = self.watchpoint_hit {
+                return Ok(hit);
+            }
+        }
+
+        // Running out of instructions is left to the next call, which reports it as an error when
+        // `HALT` is required
+        if self.finished {
+            Ok(StepOutcome::Halted)
+        } else {
+            Ok(StepOutcome::OutOfFuel)
+        }
+    }
+
+    /// Executes instructions until the register at `idx` holds `value` or the machine finishes,
+    /// returns the number of executed instructions.
+    ///
+    /// The register is checked after every instruction, so at least one instruction is executed
+    /// and calling this function again runs until the next time the register is set to the value.
+    /// Breakpoints and watched registers are ignored, the step limit set with
+    /// [`RAM::set_max_steps`] is not.
+    pub fn run_until_register(&mut self, idx: usize, value: RegisterData) -> Result<usize, RuntimeError> {
+        let mut executed = 0;
+        while self.execute_next_instruction()?.is_some() {
+            executed += 1;
+            if self.registers.get(idx).copied().unwrap_or(0) == value {
+                break;
+            }
+        }
+        Ok(executed)
+    }
+
+    fn get_register_data(&mut self, idx: usize) -> Result<RegisterData, RuntimeError> {
         if idx >= self.registers.len() {
             self.registers.resize(idx + 1, 0);
+            self.used_registers.resize(idx + 1, false);
+            self.written_registers.resize(idx + 1, false);
+        }
+
+        if self.uninitialized_policy == UninitPolicy::Error && !self.written_registers[idx] {
+            return Err(RuntimeError::UninitializedRegister { index: idx });
+        }
+
+        self.used_registers[idx] = true;
+        Ok(self.registers[idx])
+    }
+
+    /// Converts data stored in a register (or an instruction argument) into a register index.
+    /// Fails for negative indices and indices above the limits set with [`RAM::set_max_registers`]
+    /// and [`RAM::set_max_memory_cells`]
+    fn register_index(&self, data: RegisterData) -> Result<usize, RuntimeError> {
+        let Ok(idx) = usize::try_from(data) else {
+            return Err(RuntimeError::NegativeRegisterIndex { index: data });
+        };
+
+        if self.max_registers.is_some_and(|max| idx >= max) {
+            return Err(RuntimeError::RegisterLimitExceeded { index: idx });
+        }
+
+        // Only registers past the allocated ones need new cells
+        if idx >= self.registers.len() && self.max_memory_cells.is_some_and(|max| idx >= max) {
+            return Err(RuntimeError::MemoryLimitExceeded { index: idx });
         }
-        self.registers[idx]
+
+        Ok(idx)
     }
 
-    fn get_readregister_data(&mut self, idx: usize) -> RegisterData {
-        let reg_data = self.get_register_data(idx);
-        self.get_register_data(reg_data as usize)
+    fn get_readregister_data(&mut self, idx: usize) -> Result<RegisterData, RuntimeError> {
+        let reg_data = self.get_register_data(idx)?;
+        let reg_idx = self.register_index(reg_data)?;
+        self.get_register_data(reg_idx)
     }
 
     fn set_register_data(&mut self, idx: usize, data: RegisterData) {
         if idx >= self.registers.len() {
             self.registers.resize(idx + 1, 0);
+            self.used_registers.resize(idx + 1, false);
+            self.written_registers.resize(idx + 1, false);
+        }
+        self.used_registers[idx] = true;
+        self.written_registers[idx] = true;
+        self.recorded_changes.push((idx, data));
+
+        let old = self.registers[idx];
+        if old != data && self.watched_registers.contains(&idx) {
+            self.watchpoint_hit = Some(StepOutcome::WatchpointHit { register: idx, old, new: data });
         }
         self.registers[idx] = data;
     }
 
-    fn set_readregister_data(&mut self, idx: usize, data: RegisterData) {
-        let reg_idx = self.get_register_data(idx);
-        self.set_register_data(reg_idx as usize, data);
+    /// Index of the register used by the `@offset` operand, the value of the adder plus the offset
+    fn indexed_register(&mut self, offset: RegisterData) -> Result<usize, RuntimeError> {
+        let adder_data = self.get_register_data(self.adder)?;
+        self.register_index(adder_data.saturating_add(offset))
     }
 
-    fn get_instruction_data(&mut self, inst: &Instruction) -> i32 {
+    /// Resolves the index of the register that STORE, READ or SWAP writes to. `READ 5` writes to
+    /// register 5, `READ *5` writes to the register whose index is stored in register 5 and
+    /// `READ @5` to the register 5 places after the value of the adder. Every index goes through
+    /// the negative index and register limit checks
+    fn target_register(&mut self, inst: &Instruction) -> Result<usize, RuntimeError> {
         match inst.op_type {
-            OpType::Register => self.get_register_data(inst.op_value as usize),
-            OpType::Value => inst.op_value,
-            OpType::ReadReg => self.get_readregister_data(inst.op_value as usize),
-            // TODO: This should be just unreachable
-            OpType::NoValue => panic!("Instruction requires an argument"),
+            OpType::Register => self.register_index(inst.op_value),
+            OpType::ReadReg => {
+                let idx = self.register_index(inst.op_value)?;
+                let reg_data = self.get_register_data(idx)?;
+                self.register_index(reg_data)
+            }
+            OpType::Indexed => self.indexed_register(inst.op_value),
+            OpType::NoValue | OpType::Value | OpType::Relative => unreachable!("Operand types are checked before the execution"),
+        }
+    }
+
+    fn get_instruction_data(&mut self, inst: &Instruction) -> Result<RegisterData, RuntimeError> {
+        self.get_operand_data(&inst.op_type, inst.op_value)
+    }
+
+    /// Data the argument of the given type and value stands for, either the value itself or the
+    /// data of the register it points to
+    fn get_operand_data(&mut self, op_type: &OpType, op_value: RegisterData) -> Result<RegisterData, RuntimeError> {
+        match op_type {
+            OpType::Register => {
+                let idx = self.register_index(op_value)?;
+                self.get_register_data(idx)
+            }
+            OpType::Value => Ok(op_value),
+            OpType::ReadReg => {
+                let idx = self.register_index(op_value)?;
+                self.get_readregister_data(idx)
+            }
+            OpType::Indexed => {
+                let idx = self.indexed_register(op_value)?;
+                self.get_register_data(idx)
+            }
+            OpType::NoValue | OpType::Relative => unreachable!("Operand types are checked before the execution"),
+        }
+    }
+
+    /// Resolves the index of the instruction that a jump instruction points to.
+    ///
+    /// `JUMP 3` and `JUMP =3` (as well as labels) jump to the instruction with index 3, while
+    /// `JUMP *3` jumps to the instruction index stored in register 3 and `JUMP @3` to the one
+    /// stored in the register 3 places after the value of the adder. `JUMP ~-3` jumps three
+    /// instructions back from the jump itself. The target has to be an index of one of the
+    /// `program_len` instructions or point right after the last one.
+    fn get_jump_target(&mut self, inst: &Instruction, program_len: usize) -> Result<usize, RuntimeError> {
+        let target = match inst.op_type {
+            OpType::Register | OpType::Value => inst.op_value,
+            OpType::ReadReg => {
+                let idx = self.register_index(inst.op_value)?;
+                self.get_register_data(idx)?
+            }
+            OpType::Indexed => {
+                let idx = self.indexed_register(inst.op_value)?;
+                self.get_register_data(idx)?
+            }
+            OpType::Relative => {
+                // The instruction pointer already points at the instruction after the jump
+                let jump_index = (self.instruction_pointer - 1) as RegisterData;
+                jump_index.saturating_add(inst.op_value)
+            }
+            OpType::NoValue => unreachable!("Operand types are checked before the execution"),
+        };
+
+        match usize::try_from(target) {
+            Ok(index) if index <= program_len => Ok(index),
+            _ => Err(RuntimeError::JumpOutOfRange { target }),
         }
     }
+
+    #[cfg(feature = "std")]
     pub fn print_instruction_stack(&self) {
         println!("---- INSTRUCTION STACK ----");
-        for inst in &self.instruction_stack {
+        for inst in self.instruction_stack.iter() {
             println!("{inst}");
         }
         println!("---------------------------");
@@ -76,94 +1243,572 @@ impl RAM {
 
     // TODO: Put some code as an implementation function for the Instruction structure
     //
-    // TODO: All panics in this structure should be ignored. Validity check should be done on the
-    // parsing step
-    //
     /// Executes instruction under the instruction pointer and the returns it.
-    pub fn execute_next_instruction(&mut self) -> Option<Instruction> {
+    ///
+    /// Returns `Ok(None)` when the machine has finished executing. On a runtime error the
+    /// instruction pointer stays at the instruction that caused it. The failed instruction leaves
+    /// no changes behind, except for data that is not an integer, which stays consumed by `READ`.
+    /// Use [`RAM::step`] to also learn what the instruction changed.
+    pub fn execute_next_instruction(&mut self) -> Result<Option<Instruction>, RuntimeError> {
+        Ok(self.step()?.map(|info| info.instruction))
+    }
+
+    /// Executes instruction under the instruction pointer and reports what it changed: the
+    /// instruction pointer before and after it and the written registers.
+    ///
+    /// Returns `Ok(None)` when the machine has finished executing. On a runtime error the
+    /// instruction pointer stays at the instruction that caused it. The failed instruction leaves
+    /// no changes behind, except for data that is not an integer, which stays consumed by `READ`.
+    pub fn step(&mut self) -> Result<Option<StepInfo>, RuntimeError> {
         let inst_idx = self.instruction_pointer;
-        if inst_idx == self.instruction_stack.len() || self.finished {
-            self.finished = true;
-            return None
+        if inst_idx >= self.instruction_stack.len() || self.finished {
+            self.end_output_line();
+            return self.fall_off_end().map(|()| None);
         }
 
         let inst = self.instruction_stack[inst_idx].clone();
+        self.watchpoint_hit = None;
+        let result = self.execute_instruction(&inst, inst_idx, self.instruction_stack.len());
+        if result.is_err() || self.finished {
+            self.end_output_line();
+        }
+        result.map_err(|error| locate_error(error, &inst))?;
+        Ok(Some(self.step_info(inst, inst_idx)))
+    }
+
+    /// Describes the instruction with index `inst_idx` that was just executed
+    fn step_info(&self, instruction: Instruction, inst_idx: usize) -> StepInfo {
+        StepInfo {
+            step: self.steps,
+            instruction,
+            previous_pointer: inst_idx,
+            new_pointer: self.instruction_pointer,
+            accumulator: self.registers.get(self.adder).copied().unwrap_or(0),
+            changed_registers: self.recorded_changes.clone(),
+        }
+    }
+
+    /// Runs the machine until it finishes.
+    ///
+    /// Unlike [`RAM::execute_next_instruction`] the instructions are not cloned on every step,
+    /// which makes this the fastest way to execute the whole program. Breakpoints and watched
+    /// registers are ignored.
+    pub fn run(&mut self) -> Result<(), RuntimeError> {
+        // The instructions are shared with the machine for the time of the execution, so that they
+        // can be borrowed while the registers are modified. Extension handlers still see them
+        let instructions = Rc::clone(&self.instruction_stack);
+
+        let result = loop {
+            let inst_idx = self.instruction_pointer;
+            if inst_idx >= instructions.len() || self.finished {
+                break self.fall_off_end();
+            }
+
+            let inst = &instructions[inst_idx];
+            if let Err(error) = self.execute_instruction(inst, inst_idx, instructions.len()) {
+                break Err(locate_error(error, inst));
+            }
+        };
+
+        self.end_output_line();
+        result
+    }
+
+    /// Executes a single instruction with index `inst_idx` of a program made of `program_len`
+    /// instructions.
+    ///
+    /// The operand is always read before the adder is modified, so using the adder as the operand
+    /// (`ADD 0`, `MULT *1` with register 1 holding 0) sees the value from before the instruction.
+    fn execute_instruction(&mut self, inst: &Instruction, inst_idx: usize, program_len: usize) -> Result<(), RuntimeError> {
+        if !inst.op_code.accepts_operand(&inst.op_type) || !inst.has_valid_second_operand() {
+            return Err(RuntimeError::InvalidOperand { opcode: inst.op_code.clone(), index: inst_idx });
+        }
+
+        if self.max_steps.is_some_and(|max| self.steps >= max) {
+            return Err(RuntimeError::StepLimitExceeded { steps: self.steps });
+        }
+
+        self.recorded_changes.clear();
+        self.recorded_stack_change = StackChange::None;
+        let cost = self.opcode_costs.get(&inst.op_code).copied().unwrap_or(1);
+        let register_count = self.registers.len();
         self.instruction_pointer += 1;
+        self.steps += 1;
+        self.weighted_cost += cost;
 
+        if let Err(error) = self.execute_opcode(inst, program_len) {
+            // The failed instruction is not counted and the pointer stays at it, so that debuggers
+            // show the instruction that caused the error as the current one. Registers allocated
+            // by it are dropped again. Instructions fail before writing anything, except for a
+            // READ of data that is not an integer, which stays consumed since the input source
+            // cannot take it back
+            self.instruction_pointer = inst_idx;
+            self.steps -= 1;
+            self.weighted_cost -= cost;
+            self.registers.truncate(register_count);
+            self.used_registers.truncate(register_count);
+            self.written_registers.truncate(register_count);
+            return Err(error);
+        }
+
+        if self.step_warning.is_some_and(|limit| self.steps >= limit) {
+            self.step_warning_reached = true;
+        }
+
+        // The callback is moved out for the time of the call, it cannot be replaced while it runs
+        if let Some(mut callback) = self.step_callback.0.take() {
+            let info = self.step_info(inst.clone(), inst_idx);
+            if callback(&info).is_break() {
+                self.finished = true;
+            }
+            self.step_callback.0 = Some(callback);
+        }
+
+        if self.trace.is_some() {
+            let accumulator = self.registers.get(self.adder).copied().unwrap_or(0);
+            let entry = TraceEntry {
+                step: self.steps,
+                instruction_index: inst_idx,
+                instruction: inst.clone(),
+                accumulator,
+                changed_registers: self.recorded_changes.clone(),
+            };
+
+            if let Some(trace) = &mut self.trace {
+                trace.push(entry);
+            }
+        }
+
+        if let Some(history) = &mut self.accumulator_history {
+            history.push(self.registers.get(self.adder).copied().unwrap_or(0));
+        }
+
+        // The recording is moved out, so that the snapshot can be captured while it is modified
+        if let Some(mut recording) = self.recording.take() {
+            let diff = StepDiff {
+                registers: self.recorded_changes.clone(),
+                stack: self.recorded_stack_change,
+                instruction_pointer: self.instruction_pointer,
+                finished: self.finished,
+                rng_state: self.rng_state,
+            };
+            recording.record(self.steps - 1, diff, || self.snapshot());
+            self.recording = Some(recording);
+        }
+
+        Ok(())
+    }
+
+    /// Executes the operation of the instruction, the instruction pointer already points at the
+    /// next instruction
+    fn execute_opcode(&mut self, inst: &Instruction, program_len: usize) -> Result<(), RuntimeError> {
         match inst.op_code {
             OpCode::LOAD => {
-                let data = self.get_instruction_data(&inst);
-                self.set_register_data(ADDER, data);
+                let data = self.get_instruction_data(inst)?;
+                self.set_register_data(self.adder, data);
             }
             OpCode::STORE => {
-                let data = self.get_register_data(ADDER);
-                match inst.op_type {
-                    OpType::Register => self.set_register_data(inst.op_value as usize, data),
-                    OpType::ReadReg => self.set_readregister_data(inst.op_value as usize, data),
-                    OpType::NoValue | OpType::Value => panic!("Instruction STORE requires a register"),
-                };
+                let data = self.get_register_data(self.adder)?;
+                let idx = self.target_register(inst)?;
+                self.set_register_data(idx, data);
             }
             OpCode::ADD => {
-                let data = self.get_instruction_data(&inst);
-                let adder_data = self.get_register_data(ADDER);
-                self.set_register_data(ADDER, adder_data + data);
+                let data = self.get_instruction_data(inst)?;
+                let adder_data = self.get_register_data(self.adder)?;
+                self.set_arithmetic_result(adder_data.overflowing_add(data));
             }
             OpCode::SUB => {
-                let data = self.get_instruction_data(&inst);
-                let adder_data = self.get_register_data(ADDER);
-                self.set_register_data(ADDER, adder_data - data);
+                let data = self.get_instruction_data(inst)?;
+                let adder_data = self.get_register_data(self.adder)?;
+                self.set_arithmetic_result(adder_data.overflowing_sub(data));
             }
             OpCode::MULT => {
-                let data = self.get_instruction_data(&inst);
-                let adder_data = self.get_register_data(ADDER);
-                self.set_register_data(ADDER, adder_data * data);
+                let data = self.get_instruction_data(inst)?;
+                let adder_data = self.get_register_data(self.adder)?;
+                self.set_arithmetic_result(adder_data.overflowing_mul(data));
             }
             OpCode::DIV => {
-                let data = self.get_instruction_data(&inst);
-                let adder_data = self.get_register_data(ADDER);
-                self.set_register_data(ADDER, adder_data / data);
+                let data = self.get_instruction_data(inst)?;
+                if data == 0 {
+                    return Err(RuntimeError::DivisionByZero);
+                }
+                let adder_data = self.get_register_data(self.adder)?;
+                self.set_arithmetic_result(adder_data.overflowing_div(data));
             }
             OpCode::READ => {
-                // TODO: Error handling
-                let mut buffer = String::new();
-                let _ = std::io::stdin().read_line(&mut buffer);
-                let Ok(data) = buffer.trim().parse::<i32>() else {
-                    eprintln!("ERROR: Incorrect READ data: Input argument must be a 32 bit integer");
-                    return None;
-                };
+                // The target is resolved first, so that an incorrect index does not consume input
+                let idx = self.target_register(inst)?;
+                let index = self.input_count;
+                let value = self.input.read_value().ok_or(RuntimeError::UnexpectedEof { step: self.steps })?;
+                // Invalid values are consumed as well, so the ones after them keep their positions
+                self.input_count += 1;
+                let data = value.map_err(|got| RuntimeError::BadInput { got, index })?;
 
-                let register = match inst.op_type {
-                    OpType::Register => inst.op_value as usize,
-                    OpType::ReadReg => self.get_register_data(inst.op_value as usize) as usize,
-                    OpType::NoValue | OpType::Value => panic!("Instruction READ requires a register"),
-                };
-                self.set_register_data(register, data);
+                self.set_register_data(idx, data);
             }
             OpCode::WRITE => {
-                let data = self.get_instruction_data(&inst);
-                println!("{data}");
+                if self.max_output.is_some_and(|max| self.output_count >= max) {
+                    return Err(RuntimeError::OutputLimitExceeded);
+                }
+
+                let data = self.get_instruction_data(inst)?;
+                self.output_count += 1;
+                let formatted = self.output_format.format(data);
+                if self.output_line_open {
+                    self.output.write_separator(self.output_separator);
+                }
+                self.output.write_value(data, &formatted);
+                self.output_line_open = true;
+                if self.output_separator == Separator::Newline {
+                    self.end_output_line();
+                }
             }
             OpCode::JUMP => {
-                let index = self.get_instruction_data(&inst);
-                self.instruction_pointer = index as usize;
+                let index = self.get_jump_target(inst, program_len)?;
+                self.instruction_pointer = index;
             }
             OpCode::JGTZ => {
-                let adder_data = self.get_register_data(ADDER);
+                let adder_data = self.get_register_data(self.adder)?;
                 if adder_data > 0 {
-                    let index = self.get_instruction_data(&inst);
-                    self.instruction_pointer = index as usize;
+                    let index = self.get_jump_target(inst, program_len)?;
+                    self.instruction_pointer = index;
                 }
             }
             OpCode::JZERO => {
-                let adder_data = self.get_register_data(ADDER);
+                let adder_data = self.get_register_data(self.adder)?;
                 if adder_data == 0 {
-                    let index = self.get_instruction_data(&inst);
-                    self.instruction_pointer = index as usize;
+                    let index = self.get_jump_target(inst, program_len)?;
+                    self.instruction_pointer = index;
+                }
+            }
+            OpCode::JNEG => {
+                let adder_data = self.get_register_data(self.adder)?;
+                if adder_data < 0 {
+                    let index = self.get_jump_target(inst, program_len)?;
+                    self.instruction_pointer = index;
+                }
+            }
+            OpCode::JNZ => {
+                let adder_data = self.get_register_data(self.adder)?;
+                if adder_data != 0 {
+                    let index = self.get_jump_target(inst, program_len)?;
+                    self.instruction_pointer = index;
                 }
             }
+            OpCode::SWAP => {
+                let idx = self.target_register(inst)?;
+
+                let adder_data = self.get_register_data(self.adder)?;
+                let data = self.get_register_data(idx)?;
+                self.set_register_data(self.adder, data);
+                self.set_register_data(idx, adder_data);
+            }
             OpCode::HALT => self.finished = true,
-        };
-        Some(inst)
+            OpCode::PUSH => {
+                let adder_data = self.get_register_data(self.adder)?;
+                self.stack.push(adder_data);
+                self.recorded_stack_change = StackChange::Push(adder_data);
+            }
+            OpCode::POP => {
+                let data = self.stack.pop().ok_or(RuntimeError::StackUnderflow)?;
+                self.set_register_data(self.adder, data);
+                self.recorded_stack_change = StackChange::Pop;
+            }
+            OpCode::RAND => {
+                let bound = self.get_instruction_data(inst)?;
+                if bound <= 0 {
+                    return Err(RuntimeError::InvalidRandomBound { bound });
+                }
+                let data = (self.next_random() % bound as u64) as RegisterData;
+                self.set_register_data(self.adder, data);
+            }
+            OpCode::EXT => {
+                let name = inst.extension().unwrap_or_default();
+                let Some(handler) = self.extensions.0.get(name).cloned() else {
+                    return Err(RuntimeError::UnknownExtension { name: name.to_string() });
+                };
+                handler(self, inst);
+            }
+            OpCode::SET => {
+                let (op_type, value) = inst.second_operand().expect("Operands are checked before the execution");
+                let data = self.get_operand_data(op_type, value)?;
+                let idx = self.target_register(inst)?;
+                self.set_register_data(idx, data);
+            }
+            OpCode::CLEAR => {
+                // Registers are written one by one, so that the changes are traced and recorded
+                for idx in 0..self.registers.len() {
+                    let keep = self.clear_keeps_adder && idx == self.adder;
+                    if !keep && self.registers[idx] != 0 {
+                        self.set_register_data(idx, 0);
+                    }
+                }
+            }
+            OpCode::DUMP => {
+                let dump = self.dump_registers();
+                let dump = if dump.is_empty() { "all zero".to_string() } else { dump };
+                self.end_output_line();
+                self.output.write_text(&format!("DUMP: {dump}"));
+            }
+        }
+
+        Ok(())
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::VecOutput;
+    use crate::parser::Parser;
+    use crate::run::run_checked;
+
+    /// Machine with the program loaded, reading the given input
+    fn machine(source: &str, input: &[RegisterData]) -> RAM {
+        let parsed = Parser::default().parse(source).unwrap();
+        let mut ram = RAM::with_input_slice(input);
+        ram.load_instructions(parsed.instructions).unwrap();
+        ram
+    }
+
+    /// Values written by the program run on the given input, with the error that stopped it
+    fn run_output(source: &str, input: &[RegisterData]) -> (Vec<RegisterData>, Option<RuntimeError>) {
+        let report = run_checked(source, input).unwrap();
+        (report.output, report.error)
+    }
+
+    #[test]
+    fn pointer_stays_at_failed_instruction() {
+        let mut ram = machine("LOAD =1\nDIV =0\nHALT", &[]);
+        ram.step().unwrap();
+
+        let error = ram.step().unwrap_err();
+        assert_eq!(error.root(), &RuntimeError::DivisionByZero);
+        assert_eq!(ram.instruction_pointer(), 1);
+        assert_eq!(ram.step_count(), 1);
+        assert_eq!(ram.current_instruction().map(Instruction::op_code), Some(&OpCode::DIV));
+    }
+
+    #[test]
+    fn failed_instruction_does_not_allocate_registers() {
+        let mut ram = machine("LOAD =1\nDIV 10\nHALT", &[]);
+        ram.step().unwrap();
+        let register_count = ram.registers().len();
+
+        assert_eq!(ram.step().unwrap_err().root(), &RuntimeError::DivisionByZero);
+        assert_eq!(ram.registers().len(), register_count);
+    }
+
+    #[test]
+    fn failed_write_is_not_counted() {
+        let mut ram = machine("WRITE *1\nHALT", &[]);
+        ram.set_output(Box::new(VecOutput::default()));
+        ram.set_max_output(1);
+        ram.set_register(1, -1);
+
+        assert_eq!(ram.step().unwrap_err().root(), &RuntimeError::NegativeRegisterIndex { index: -1 });
+        ram.set_register(1, 0);
+        assert!(ram.step().is_ok());
+    }
+
+    #[test]
+    fn store_through_negative_index() {
+        let mut ram = machine("LOAD =7\nSTORE *1\nHALT", &[]);
+        ram.set_register(1, -1);
+        ram.step().unwrap();
+
+        assert_eq!(ram.step().unwrap_err().root(), &RuntimeError::NegativeRegisterIndex { index: -1 });
+        assert_eq!(ram.registers(), [7, -1]);
+
+        let (_, error) = run_output("LOAD =-1\nSTORE 1\nSTORE *1\nHALT", &[]);
+        assert_eq!(error.as_ref().map(RuntimeError::root), Some(&RuntimeError::NegativeRegisterIndex { index: -1 }));
+    }
+
+    #[test]
+    fn read_through_negative_index() {
+        let mut ram = machine("READ *1\nHALT", &[5]);
+        ram.set_register(1, -1);
+
+        assert_eq!(ram.step().unwrap_err().root(), &RuntimeError::NegativeRegisterIndex { index: -1 });
+        assert_eq!(ram.registers(), [0, -1]);
+
+        // The input is not consumed by the failed instruction
+        ram.set_register(1, 2);
+        ram.step().unwrap();
+        assert_eq!(ram.registers(), [0, 2, 5]);
+
+        let (_, error) = run_output("LOAD =-1\nSTORE 1\nREAD *1\nHALT", &[5]);
+        assert_eq!(error.as_ref().map(RuntimeError::root), Some(&RuntimeError::NegativeRegisterIndex { index: -1 }));
+    }
+
+    #[test]
+    fn read_through_pointer() {
+        let mut ram = machine("READ *2\nHALT", &[42]);
+        ram.set_register(2, 4);
+        ram.run().unwrap();
+
+        assert_eq!(ram.registers(), [0, 0, 4, 0, 42]);
+
+        let source = "LOAD =5\nSTORE 2\nREAD *2\nWRITE 5\nHALT";
+        assert_eq!(run_output(source, &[42]), (vec![42], None));
+    }
+
+    #[test]
+    fn adder_as_register_zero() {
+        let cases = [
+            ("LOAD =5\nADD 0", 10),
+            ("LOAD =5\nSUB 0", 0),
+            ("LOAD =5\nMULT 0", 25),
+            ("LOAD =5\nDIV 0", 1),
+            ("LOAD =5\nLOAD 0", 5),
+            ("LOAD =5\nSTORE 0\nADD 0", 10),
+            ("LOAD =5\nSWAP 0", 5),
+            ("LOAD =5\nSET 0, 0\nADD 0", 10),
+        ];
+
+        for (source, adder) in cases {
+            let mut ram = machine(source, &[]);
+            ram.run().unwrap();
+            assert_eq!(ram.registers()[0], adder, "source: {source:?}");
+        }
+    }
+
+    #[test]
+    fn extensions_see_program_during_run() {
+        let mut parser = Parser::default();
+        parser.allow_extension("COUNT");
+        let parsed = parser.parse("LOAD =1\nCOUNT\nHALT").unwrap();
+
+        let mut ram = RAM::new();
+        ram.register_extension("COUNT", Box::new(|ram, _| {
+            let stats = ram.stats();
+            ram.set_register(1, ram.instructions().len() as RegisterData);
+            ram.set_register(2, stats.instruction_count as RegisterData);
+            ram.set_register(3, stats.steps as RegisterData);
+        }));
+        ram.load_instructions(parsed.instructions).unwrap();
+        ram.run().unwrap();
+
+        assert_eq!(ram.registers(), [1, 3, 3, 2]);
+        assert_eq!(ram.instructions().len(), 3);
+    }
+
+    #[test]
+    fn operand_signs() {
+        let source = "LOAD =5\nSTORE 5\nWRITE =5\nWRITE =-5\nWRITE =+5\nWRITE 5\nWRITE +5\nHALT";
+        assert_eq!(run_output(source, &[]), (vec![5, -5, 5, 5, 5], None));
+    }
+
+    #[test]
+    fn stack_keeps_intermediate_results() {
+        // (2 + 3) * (4 + 1), the second POP finds the stack empty
+        let source = "LOAD =2\nADD =3\nPUSH\nLOAD =4\nADD =1\nSTORE 1\nPOP\nMULT 1\nWRITE 0\nPOP\nHALT";
+        let (output, error) = run_output(source, &[]);
+
+        assert_eq!(output, [25]);
+        assert_eq!(error.as_ref().map(RuntimeError::root), Some(&RuntimeError::StackUnderflow));
+    }
+
+    #[test]
+    fn random_values_depend_only_on_seed() {
+        // Five dice rolls
+        let source = "LOAD =5\nSTORE 1\nroll: RAND =6\nADD =1\nWRITE 0\nLOAD 1\nSUB =1\nSTORE 1\nJGTZ roll\nHALT";
+        let rolls = |seed| {
+            let mut ram = machine(source, &[]);
+            let output = VecOutput::default();
+            ram.set_output(Box::new(output.clone()));
+            ram.set_seed(seed);
+            ram.run().unwrap();
+            output.values()
+        };
+
+        let first = rolls(7);
+        assert_eq!(first.len(), 5);
+        assert!(first.iter().all(|roll| (1..=6).contains(roll)), "rolls: {first:?}");
+        assert_eq!(rolls(7), first);
+        assert_ne!(rolls(8), first);
+
+        let (_, error) = run_output("RAND =0\nHALT", &[]);
+        assert_eq!(error.as_ref().map(RuntimeError::root), Some(&RuntimeError::InvalidRandomBound { bound: 0 }));
+    }
+
+    #[test]
+    fn indexed_operands_walk_an_array() {
+        // Writes the array stored in registers 10 to 12, register 1 holds the current index
+        let source = "LOAD =7 | STORE 10\nLOAD =8 | STORE 11\nLOAD =9 | STORE 12\nLOAD =0\n\
+            loop: STORE 1\nLOAD @10\nWRITE 0\nLOAD 1\nADD =1\nSUB =3\nJZERO end\nADD =3\nJUMP loop\nend: HALT";
+        assert_eq!(run_output(source, &[]), (vec![7, 8, 9], None));
+
+        let mut ram = machine("LOAD =2\nSTORE @1\nLOAD @1\nHALT", &[]);
+        ram.run().unwrap();
+        assert_eq!(ram.registers(), [2, 0, 0, 2]);
+    }
+
+    #[test]
+    fn relative_jumps() {
+        // Counts down from 3 jumping back two instructions, then skips over a `WRITE` forward
+        let source = "LOAD =3\nWRITE 0\nSUB =1\nJGTZ ~-2\nJUMP ~2\nWRITE =9\nHALT";
+        assert_eq!(run_output(source, &[]), (vec![3, 2, 1], None));
+    }
+
+    #[test]
+    fn clear_resets_registers_but_not_stack() {
+        // Sums 1 to 3 twice, the number of runs left is kept on the stack
+        let source = "LOAD =2\nPUSH\nagain: CLEAR\nLOAD =3\nSTORE 1\n\
+            loop: LOAD 2\nADD 1\nSTORE 2\nLOAD 1\nSUB =1\nSTORE 1\nJGTZ loop\n\
+            WRITE 2\nPOP\nSUB =1\nPUSH\nJGTZ again\nHALT";
+        assert_eq!(run_output(source, &[]), (vec![6, 6], None));
+
+        let mut ram = machine("LOAD =4\nSTORE 3\nCLEAR\nHALT", &[]);
+        ram.run().unwrap();
+        assert!(ram.registers().iter().all(|&data| data == 0), "registers: {:?}", ram.registers());
+    }
+
+    #[test]
+    fn set_writes_registers_directly() {
+        let source = "LOAD =7\nSET 1, =10\nSET 2, =-3\nSET *1, =99\nSET 3, 10\nWRITE 0\nWRITE 1\nWRITE 2\nWRITE 3\nHALT";
+        assert_eq!(run_output(source, &[]), (vec![7, 10, -3, 99], None));
+
+        let mut ram = machine(source, &[]);
+        ram.run().unwrap();
+        assert_eq!(ram.registers()[10], 99);
+    }
+
+    #[test]
+    fn bad_input_index_counts_consumed_values() {
+        let mut ram = machine("loop: READ 1\nJUMP loop", &[]);
+        ram.set_input(Box::new(VecInput::from_text("1 x 2 y")));
+
+        // A failed `READ` keeps the instruction pointer, so the next step reads the next value
+        let steps = [
+            None,
+            None,
+            Some(RuntimeError::BadInput { got: "x".into(), index: 1 }),
+            None,
+            None,
+            Some(RuntimeError::BadInput { got: "y".into(), index: 3 }),
+            Some(RuntimeError::UnexpectedEof { step: 5 }),
+        ];
+        for expected in steps {
+            let error = ram.step().err();
+            assert_eq!(error.as_ref().map(RuntimeError::root), expected.as_ref());
+        }
+    }
+
+    #[test]
+    fn run_steps_reports_falling_off_end() {
+        let mut ram = machine("LOAD =1", &[]);
+        assert_eq!(ram.run_steps(1), Ok(StepOutcome::OutOfFuel));
+        assert_eq!(ram.run_steps(1), Ok(StepOutcome::Halted));
+
+        let mut ram = machine("LOAD =1", &[]);
+        ram.set_require_halt(true);
+        assert_eq!(ram.run_steps(1), Ok(StepOutcome::OutOfFuel));
+        assert_eq!(ram.run_steps(1).unwrap_err().root(), &RuntimeError::FellOffEnd);
+
+        let mut ram = machine("LOAD =1\nHALT", &[]);
+        ram.set_require_halt(true);
+        assert_eq!(ram.run_steps(2), Ok(StepOutcome::Halted));
+    }
+}
@@ -0,0 +1,193 @@
+use std::io::Write;
+
+use RAMulator::ram::{StepOutcome, RAM};
+
+/// Number of steps after which the debugger warns that the program may be looping
+const STEP_WARNING: usize = 1_000_000;
+
+/// Number of registers printed by the `mem` command
+const MEMORY_PAGE: usize = 8;
+
+const HELP: &str = "\
+Commands:
+    step          Execute the next instruction
+    run           Restart the program and run until a breakpoint or the end
+    continue      Run from the current instruction until a breakpoint or the end
+    back          Go back by one step
+    goto <N>      Go to the state after N steps, forward or backward
+    regs [all]    Print registers holding non-zero values (or all of them)
+    mem <N>       Print 8 registers starting at register N
+    state         Print the state of the machine
+    stats         Print the program size, registers used and steps taken
+    break <N>     Toggle a breakpoint on the instruction with index N or label N
+    watch <N>     Toggle pausing the execution when register N changes
+    reset         Bring the machine back to its initial state
+    list          Print the instruction stack
+    help          Print this message
+    quit          Exit the debugger";
+
+/// Prints the instruction that is going to be executed next
+fn print_position(ram: &RAM) {
+    match ram.current_instruction() {
+        Some(inst) if !ram.is_finished() => println!("[{}] {inst}", ram.instruction_pointer()),
+        _ => println!("Program finished"),
+    }
+}
+
+fn print_registers(ram: &RAM, include_zeros: bool) {
+    let dump = if include_zeros {
+        ram.dump_all_registers()
+    } else {
+        ram.dump_registers()
+    };
+
+    if dump.is_empty() {
+        println!("All registers are zero");
+    } else {
+        println!("{dump}");
+    }
+}
+
+fn print_stats(ram: &RAM) {
+    let stats = ram.stats();
+    println!("Instructions:       {}", stats.instruction_count);
+    match stats.highest_register {
+        Some(idx) => println!("Highest register:   R{idx}"),
+        None => println!("Highest register:   none"),
+    }
+    println!("Distinct registers: {}", stats.distinct_registers);
+    println!("Steps:              {}", stats.steps);
+    println!("Weighted cost:      {}", stats.weighted_cost);
+}
+
+/// Executes a single instruction, returns `false` when the machine cannot continue
+fn step(ram: &mut RAM) -> bool {
+    match ram.step() {
+        Ok(Some(info)) => {
+            let changes: Vec<String> = info
+                .changed_registers
+                .iter()
+                .map(|(idx, value)| format!("R{idx} = {value}"))
+                .collect();
+            if changes.is_empty() {
+                println!("Executed: {}", info.instruction);
+            } else {
+                println!("Executed: {} ({})", info.instruction, changes.join(", "));
+            }
+            true
+        }
+        Ok(None) => false,
+        Err(error) => {
+            eprintln!("{error}");
+            false
+        }
+    }
+}
+
+/// Executes instructions until a breakpoint is reached or the machine stops
+fn continue_execution(ram: &mut RAM) {
+    // Always execute at least one instruction so that the debugger can leave a breakpoint
+    let mut warned = ram.step_warning_reached();
+    while step(ram) && !ram.is_finished() && !ram.at_breakpoint() && ram.watchpoint_hit().is_none() {
+        if !warned && ram.step_warning_reached() {
+            eprintln!("WARNING: The program has run {STEP_WARNING} steps, it may be looping");
+            warned = true;
+        }
+    }
+
+    if let Some(StepOutcome::WatchpointHit { register, old, new }) = ram.watchpoint_hit() {
+        println!("Watchpoint hit: R{register} changed from {old} to {new}");
+    } else if ram.at_breakpoint() && !ram.is_finished() {
+        print!("Breakpoint hit: ");
+    }
+    print_position(ram);
+}
+
+/// Runs an interactive debugging session, reading commands from stdin
+pub fn run_debugger(mut ram: RAM) {
+    ram.enable_recording();
+    ram.set_step_warning(STEP_WARNING);
+    println!("Type `help` to list available commands");
+    print_position(&ram);
+
+    // Stdin must not stay locked, the READ instruction reads from it as well
+    let mut line = String::new();
+    loop {
+        print!("(ram) ");
+        let _ = std::io::stdout().flush();
+
+        line.clear();
+        match std::io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        let line = line.trim();
+
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (None, _) => {}
+            (Some("step" | "s"), None) => {
+                step(&mut ram);
+                print_position(&ram);
+            }
+            (Some("run" | "r"), None) => {
+                ram.reset();
+                continue_execution(&mut ram);
+            }
+            (Some("continue" | "c"), None) => continue_execution(&mut ram),
+            (Some("back"), None) => match ram.step_count().checked_sub(1) {
+                Some(step) if ram.goto_step(step) => print_position(&ram),
+                _ => eprintln!("ERROR: There is no earlier step to go back to"),
+            },
+            (Some("goto"), Some(step)) => match step.parse::<usize>() {
+                Ok(step) if ram.goto_step(step) => print_position(&ram),
+                _ => {
+                    let (first, last) = ram.recorded_steps().unwrap_or_default();
+                    eprintln!("ERROR: `{step}` is not a recorded step, steps {first} to {last} were recorded");
+                }
+            },
+            (Some("regs"), None) => print_registers(&ram, false),
+            (Some("regs"), Some("all")) => print_registers(&ram, true),
+            (Some("mem"), Some(start)) => match start.parse::<usize>() {
+                Ok(start) => {
+                    for (idx, value) in ram.memory_view(start, MEMORY_PAGE) {
+                        println!("R{idx} = {value}");
+                    }
+                }
+                _ => eprintln!("ERROR: `{start}` is not a valid register index"),
+            },
+            (Some("break" | "b"), Some(target)) => match target.parse().ok().or_else(|| ram.label_index(target)) {
+                Some(idx) if idx < ram.instructions().len() => {
+                    if ram.remove_breakpoint(idx) {
+                        println!("Removed breakpoint at instruction {idx}");
+                    } else {
+                        ram.add_breakpoint(idx);
+                        println!("Added breakpoint at instruction {idx}");
+                    }
+                }
+                _ => eprintln!("ERROR: `{target}` is not a valid instruction index or label"),
+            },
+            (Some("watch" | "w"), Some(index)) => match index.parse::<usize>() {
+                Ok(idx) => {
+                    if ram.unwatch_register(idx) {
+                        println!("Stopped watching R{idx}");
+                    } else {
+                        ram.watch_register(idx);
+                        println!("Watching R{idx}");
+                    }
+                }
+                _ => eprintln!("ERROR: `{index}` is not a valid register index"),
+            },
+            (Some("state"), None) => println!("{ram}"),
+            (Some("stats"), None) => print_stats(&ram),
+            (Some("reset"), None) => {
+                ram.reset();
+                print_position(&ram);
+            }
+            (Some("list"), None) => ram.print_instruction_stack(),
+            (Some("help"), None) => println!("{HELP}"),
+            (Some("quit" | "q"), None) => break,
+            _ => eprintln!("ERROR: Unknown command `{line}`. Type `help` to list available commands"),
+        }
+    }
+}
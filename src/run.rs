@@ -0,0 +1,53 @@
+use alloc::{boxed::Box, vec, vec::Vec};
+
+use crate::analysis::Warning;
+use crate::io::{VecInput, VecOutput};
+use crate::parser::{validate, ParseError, Parser};
+use crate::ram::{RegisterData, RuntimeError, RAM};
+
+/// Number of instructions a program run with [`run_checked`] may execute, prevents programs that
+/// never finish from hanging the caller
+pub const CHECKED_STEP_LIMIT: usize = 1_000_000;
+
+/// Everything known about a finished run of [`run_checked`]
+#[derive(Debug, PartialEq, Eq)]
+pub struct RunReport {
+    /// Values written by the program, including the ones written before a runtime error
+    pub output: Vec<RegisterData>,
+    /// Registers of the machine after the run, including the adder
+    pub registers: Vec<RegisterData>,
+    /// Number of executed instructions
+    pub steps: usize,
+    /// Non-fatal problems found in the source before the run
+    pub warnings: Vec<Warning>,
+    /// Error that stopped the program, `None` when it finished normally
+    pub error: Option<RuntimeError>,
+}
+
+/// Validates the source and, when it has no errors, runs it on the given input.
+///
+/// All errors found by [`validate`] are returned at once and the machine is not built. Warnings do
+/// not stop the program, they are returned in the report together with its results. The run is
+/// limited to [`CHECKED_STEP_LIMIT`] steps.
+pub fn run_checked(source: &str, input: &[RegisterData]) -> Result<RunReport, Vec<ParseError>> {
+    validate(source)?;
+    let parsed = Parser::default().parse(source).map_err(|error| vec![error])?;
+
+    let output = VecOutput::default();
+    let mut ram = RAM::builder()
+        .input(Box::new(VecInput::new(input)))
+        .output(Box::new(output.clone()))
+        .max_steps(CHECKED_STEP_LIMIT)
+        .build();
+    ram.load_instructions(parsed.instructions).map_err(|error| vec![error])?;
+    ram.set_entry_point(parsed.entry_point);
+
+    let error = ram.run().err();
+    Ok(RunReport {
+        output: output.values(),
+        registers: ram.registers().to_vec(),
+        steps: ram.step_count(),
+        warnings: parsed.warnings,
+        error,
+    })
+}
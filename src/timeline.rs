@@ -0,0 +1,106 @@
+use alloc::{vec, vec::Vec};
+
+use crate::ram::{MachineState, RegisterData};
+
+/// Number of steps between two full snapshots of the machine. States in between are rebuilt by
+/// applying the recorded changes to the closest earlier snapshot
+pub const KEYFRAME_INTERVAL: usize = 256;
+
+/// Change of the stack made by a single executed instruction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum StackChange {
+    #[default]
+    None,
+    Push(RegisterData),
+    Pop,
+}
+
+/// Changes made to the machine by a single executed instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct StepDiff {
+    /// Registers written by the instruction together with their new values
+    pub registers: Vec<(usize, RegisterData)>,
+    pub stack: StackChange,
+    /// Instruction pointer after the instruction was executed
+    pub instruction_pointer: usize,
+    /// State of the machine after the instruction was executed
+    pub finished: bool,
+    /// State of the random number generator after the instruction was executed
+    pub rng_state: u64,
+}
+
+/// Complete record of a run, allows to bring the machine to any of the recorded steps
+#[derive(Debug, Clone)]
+pub(crate) struct Recording {
+    /// Step the recording begins at
+    start_step: usize,
+    /// Full snapshots taken every [`KEYFRAME_INTERVAL`] steps, starting at `start_step`
+    keyframes: Vec<MachineState>,
+    /// Changes made by every recorded step
+    diffs: Vec<StepDiff>,
+}
+
+impl Recording {
+    /// Starts recording at the given state
+    pub fn new(state: MachineState) -> Self {
+        Self { start_step: state.steps, keyframes: vec![state], diffs: Vec::new() }
+    }
+
+    /// First and last recorded step
+    pub fn steps(&self) -> (usize, usize) {
+        (self.start_step, self.start_step + self.diffs.len())
+    }
+
+    /// Records changes made by the instruction executed in `step` (counting from 0). When the
+    /// machine was brought back to an earlier step, the recorded future is dropped, since it no
+    /// longer happens. `snapshot` captures the state after the instruction was executed
+    pub fn record(&mut self, step: usize, diff: StepDiff, snapshot: impl FnOnce() -> MachineState) {
+        let offset = match step.checked_sub(self.start_step) {
+            Some(offset) if offset <= self.diffs.len() => offset,
+            // The machine was moved outside of the recording, so the recording starts over
+            _ => {
+                *self = Self::new(snapshot());
+                return;
+            }
+        };
+
+        self.diffs.truncate(offset);
+        self.keyframes.truncate(offset / KEYFRAME_INTERVAL + 1);
+
+        self.diffs.push(diff);
+        if self.diffs.len().is_multiple_of(KEYFRAME_INTERVAL) {
+            self.keyframes.push(snapshot());
+        }
+    }
+
+    /// Rebuilds the state of the machine after `step` steps, returns `None` if the step was not
+    /// recorded
+    pub fn state_at(&self, step: usize) -> Option<MachineState> {
+        let offset = step.checked_sub(self.start_step).filter(|offset| *offset <= self.diffs.len())?;
+
+        let keyframe = offset / KEYFRAME_INTERVAL;
+        let mut state = self.keyframes.get(keyframe)?.clone();
+
+        for diff in &self.diffs[keyframe * KEYFRAME_INTERVAL..offset] {
+            for &(idx, data) in &diff.registers {
+                if idx >= state.registers.len() {
+                    state.registers.resize(idx + 1, 0);
+                }
+                state.registers[idx] = data;
+            }
+            match diff.stack {
+                StackChange::None => {}
+                StackChange::Push(data) => state.stack.push(data),
+                StackChange::Pop => {
+                    state.stack.pop();
+                }
+            }
+            state.instruction_pointer = diff.instruction_pointer;
+            state.finished = diff.finished;
+            state.rng_state = diff.rng_state;
+            state.steps += 1;
+        }
+
+        Some(state)
+    }
+}
@@ -0,0 +1,112 @@
+use alloc::{format, string::String, vec::Vec};
+
+use crate::Instruction;
+use crate::ram::RegisterData;
+
+/// Record of a single executed instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// Number of the step, starting from 1
+    pub step: usize,
+    /// Index of the executed instruction
+    pub instruction_index: usize,
+    /// Executed instruction
+    pub instruction: Instruction,
+    /// Value of the adder after the instruction was executed
+    pub accumulator: RegisterData,
+    /// Registers written by the instruction and their new values, in the order of writing
+    pub changed_registers: Vec<(usize, RegisterData)>,
+}
+
+/// Quotes the CSV field, doubling quotes inside of it
+fn csv_field(field: &str) -> String {
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
+
+/// Converts the trace into CSV with the columns: step, instruction_index, opcode, operand,
+/// accumulator_after and changed_registers (for example `R0=5 R3=-1`)
+pub fn trace_to_csv(trace: &[TraceEntry]) -> String {
+    let mut csv = String::from("step,instruction_index,opcode,operand,accumulator_after,changed_registers\n");
+    for entry in trace {
+        let opcode = entry.instruction.mnemonic();
+        let operand = entry.instruction.operand_text();
+        let changed: Vec<String> = entry.changed_registers
+            .iter()
+            .map(|(idx, data)| format!("R{idx}={data}"))
+            .collect();
+
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            entry.step,
+            entry.instruction_index,
+            csv_field(&opcode),
+            csv_field(&operand),
+            entry.accumulator,
+            csv_field(&changed.join(" ")),
+        ));
+    }
+    csv
+}
+
+/// First step at which two traces stop agreeing, found by [`diff_traces`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceDivergence {
+    /// Number of the step counted from the beginning of the traces, starting from 1
+    pub step: usize,
+    /// Entry of the first trace at that step, `None` when the first trace ended before it
+    pub left: Option<TraceEntry>,
+    /// Entry of the second trace at that step, `None` when the second trace ended before it
+    pub right: Option<TraceEntry>,
+}
+
+impl core::fmt::Display for TraceDivergence {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "Traces diverge at step {}", self.step)?;
+        writeln!(f, "First:  {}", format_entry(self.left.as_ref()))?;
+        write!(f, "Second: {}", format_entry(self.right.as_ref()))
+    }
+}
+
+fn format_entry(entry: Option<&TraceEntry>) -> String {
+    match entry {
+        Some(entry) => format!(
+            "[{}] {} {} (adder = {})",
+            entry.instruction_index,
+            entry.instruction.mnemonic(),
+            entry.instruction.operand_text(),
+            entry.accumulator,
+        ),
+        None => String::from("program finished"),
+    }
+}
+
+/// Compares two traces step by step and returns the first step where they executed a different
+/// instruction or left a different value in the adder. A trace that ends while the other one goes
+/// on diverges at its first missing step. Returns `None` for identical executions.
+///
+/// Useful to point at the step where a program stops behaving like a reference solution.
+pub fn diff_traces(left: &[TraceEntry], right: &[TraceEntry]) -> Option<TraceDivergence> {
+    let diverged = left
+        .iter()
+        .zip(right)
+        .position(|(a, b)| a.instruction_index != b.instruction_index || a.accumulator != b.accumulator);
+
+    if let Some(position) = diverged {
+        return Some(TraceDivergence {
+            step: position + 1,
+            left: Some(left[position].clone()),
+            right: Some(right[position].clone()),
+        });
+    }
+
+    if left.len() == right.len() {
+        return None;
+    }
+
+    let position = left.len().min(right.len());
+    Some(TraceDivergence {
+        step: position + 1,
+        left: left.get(position).cloned(),
+        right: right.get(position).cloned(),
+    })
+}
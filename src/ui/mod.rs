@@ -1,10 +1,74 @@
 use eframe::egui;
 
+use crate::analysis::Warning;
+use crate::error::RamError;
+use crate::format::format_source;
+use crate::parser::{parse_metadata, Parser};
+
+/// Example programs from the `ram` directory, embedded so that they ship with the binary
+const EXAMPLES: &[(&str, &str)] = &[
+    ("add_numbers", include_str!("../../ram/add_numbers.ram")),
+    ("sequence_sum", include_str!("../../ram/sequence_sum.ram")),
+    ("power", include_str!("../../ram/power.ram")),
+    ("example", include_str!("../../ram/example.ram")),
+    ("constants", include_str!("../../ram/constants.ram")),
+    ("jump_table", include_str!("../../ram/jump_table.ram")),
+    ("accumulator", include_str!("../../ram/accumulator.ram")),
+    ("dump", include_str!("../../ram/dump.ram")),
+];
+
 #[derive(Default)]
 struct MyApp {
     clicked: bool,
     da_input: String,
     cursor: usize,
+    /// Index of the example program picked from [`EXAMPLES`]
+    example: Option<usize>,
+    /// Error shown under the code editor, cleared by the next successful action
+    error: Option<RamError>,
+    /// Source the `warnings` were found in, the program is parsed again once it is edited
+    checked_source: String,
+    /// Warnings of the edited program, shown under the code editor
+    warnings: Vec<Warning>,
+}
+
+impl MyApp {
+    /// Parses the edited program again when it changed since the warnings were last found. A
+    /// program that cannot be parsed has no warnings
+    fn update_warnings(&mut self) {
+        if self.checked_source == self.da_input {
+            return;
+        }
+
+        let mut parser = Parser::default();
+        self.warnings = match parser.parse(&self.da_input) {
+            Ok(_) => parser.warnings().to_vec(),
+            Err(_) => Vec::new(),
+        };
+        self.checked_source = self.da_input.clone();
+    }
+}
+
+/// Title of the example program, the name from its metadata or the name of its file
+fn example_title(idx: usize) -> String {
+    let (file_name, source) = EXAMPLES[idx];
+    parse_metadata(source).name.unwrap_or_else(|| file_name.to_string())
+}
+
+/// Shows the code editor with a gutter of line numbers (starting from 1) on its left. Both are
+/// placed in a single scroll area, so that they scroll together
+fn code_editor_with_line_numbers(ui: &mut egui::Ui, id: &str, code: &mut String) {
+    // An empty last line is not returned by `lines`, but the editor still shows it
+    let line_count = code.lines().count().max(1) + usize::from(code.ends_with('\n'));
+    let width = line_count.to_string().len();
+    let numbers: Vec<String> = (1..=line_count).map(|line| format!("{line:>width$}")).collect();
+
+    egui::ScrollArea::vertical().id_source(id).show(ui, |ui| {
+        ui.horizontal_top(|ui| {
+            ui.add(egui::Label::new(egui::RichText::new(numbers.join("\n")).monospace().weak()));
+            ui.code_editor(code);
+        });
+    });
 }
 
 pub fn run_app() -> Result<(), eframe::Error> {
@@ -25,7 +89,41 @@ impl eframe::App for MyApp {
         // egui::CentralPanel::default().show(ctx, |_| {
         egui::Window::new("test").resizable(true).show(ctx, |ui| {
             ui.label("show da window");
-            let _  = ui.add(egui::TextEdit::multiline(&mut self.da_input));
+
+            let previous = self.example;
+            let selected = self.example.map_or("Load an example".to_string(), example_title);
+            egui::ComboBox::from_label("Examples")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for idx in 0..EXAMPLES.len() {
+                        ui.selectable_value(&mut self.example, Some(idx), example_title(idx));
+                    }
+                });
+
+            if let Some(idx) = self.example.filter(|_| self.example != previous) {
+                self.da_input = EXAMPLES[idx].1.to_string();
+                self.cursor = 0;
+            }
+
+            code_editor_with_line_numbers(ui, "input", &mut self.da_input);
+            if ui.button("Format").clicked() {
+                match format_source(&self.da_input) {
+                    Ok(formatted) => {
+                        self.da_input = formatted;
+                        self.error = None;
+                    }
+                    Err(error) => self.error = Some(error.into()),
+                }
+            }
+            if let Some(error) = &self.error {
+                ui.label(error.to_string());
+            }
+
+            self.update_warnings();
+            for warning in &self.warnings {
+                ui.colored_label(egui::Color32::YELLOW, warning.to_string());
+            }
+
             if ui.button("show").clicked() {
                 self.clicked = !self.clicked;
             }
@@ -52,7 +150,7 @@ impl eframe::App for MyApp {
                 }
 
                 let mut textbox_string = lines.join("\n");
-                ui.code_editor(&mut textbox_string);
+                code_editor_with_line_numbers(ui, "stepper", &mut textbox_string);
                 ui.label("crash");
                 if ui.button("123").clicked() {
                     panic!()
@@ -61,3 +159,17 @@ impl eframe::App for MyApp {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::run_checked;
+
+    #[test]
+    fn examples_load_and_run() {
+        for (name, source) in EXAMPLES {
+            let report = run_checked(source, &[3, 4, 5, 6]).unwrap_or_else(|errors| panic!("{name}: {errors:?}"));
+            assert_eq!(report.error, None, "{name}");
+        }
+    }
+}